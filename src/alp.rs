@@ -0,0 +1,354 @@
+//! ALP, from "ALP: Adaptive Lossless floating-Point Compression" (Afroozeh et
+//! al., 2023).
+//!
+//! Doubles that were parsed from decimals, like `149.8697`, become integers
+//! when multiplied by a power of ten: `n = round(v * 10^e * 10^-f)`, and
+//! `n * 10^f * 10^-e` gives `v` back. The factor `f` drops trailing decimal
+//! zeros that every value of the page shares. Each page picks the `(e, f)` that
+//! gives the smallest output on a sample of its values. Values that do not come
+//! back bit for bit, such as NaN, -0.0 or values with too many digits, are
+//! stored separately as exceptions and their slots take the first encoded
+//! integer so they do not widen the range. The integers are stored as their
+//! difference to the page minimum (frame of reference) in as many bits as the
+//! largest difference needs.
+//!
+//! A page holds, all little endian:
+//!
+//! * the value count (u32), `e` (u8), `f` (u8) and the bit width (u8)
+//! * the page minimum (i64)
+//! * the exception count (u32), then each exception as its position (u32)
+//!   and its bits (u64)
+//! * the packed differences, most significant bit first
+//!
+//! Unlike the paper, the sample is a fixed number of evenly spaced values of
+//! the page rather than a two-level sample across row groups.
+
+use bit_streamer::{Reader, Writer};
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io;
+
+const MAX_EXPONENT: usize = 18;
+const SAMPLE_SIZE: usize = 256;
+const HEADER_BYTES: usize = 4 + 3 + 8 + 4;
+const EXCEPTION_BYTES: usize = 4 + 8;
+
+// 10^18 is the largest power of ten below i64::MAX, all of these are exact
+const POWERS_OF_TEN: [f64; MAX_EXPONENT + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18,
+];
+const INVERSE_POWERS_OF_TEN: [f64; MAX_EXPONENT + 1] = [
+    1e0, 1e-1, 1e-2, 1e-3, 1e-4, 1e-5, 1e-6, 1e-7, 1e-8, 1e-9, 1e-10, 1e-11, 1e-12, 1e-13, 1e-14,
+    1e-15, 1e-16, 1e-17, 1e-18,
+];
+
+fn decode_one(n: i64, exponent: usize, factor: usize) -> f64 {
+    n as f64 * POWERS_OF_TEN[factor] * INVERSE_POWERS_OF_TEN[exponent]
+}
+
+// the integer for `value`, or None if it does not decode to the same bits
+fn encode_one(value: f64, exponent: usize, factor: usize) -> Option<i64> {
+    let scaled = (value * POWERS_OF_TEN[exponent] * INVERSE_POWERS_OF_TEN[factor]).round();
+    if !scaled.is_finite() || scaled.abs() >= i64::MAX as f64 {
+        return None;
+    }
+    let n = scaled as i64;
+    if decode_one(n, exponent, factor).to_bits() == value.to_bits() {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+fn bit_width(range: u64) -> u32 {
+    64 - range.leading_zeros()
+}
+
+// estimated page size in bits for a sample encoded with `exponent` and `factor`
+fn estimate(sample: &[f64], exponent: usize, factor: usize) -> usize {
+    let mut exceptions = 0;
+    let mut min = i64::MAX;
+    let mut max = i64::MIN;
+    for &value in sample {
+        match encode_one(value, exponent, factor) {
+            Some(n) => {
+                min = min.min(n);
+                max = max.max(n);
+            }
+            None => exceptions += 1,
+        }
+    }
+    let width = if min > max {
+        0
+    } else {
+        bit_width(max.wrapping_sub(min) as u64) as usize
+    };
+    sample.len() * width + exceptions * EXCEPTION_BYTES * 8
+}
+
+// the exponent and factor with the smallest estimate on evenly spaced values
+fn choose(src: &[f64]) -> (usize, usize) {
+    let step = (src.len() / SAMPLE_SIZE).max(1);
+    let sample: Vec<f64> = src.iter().step_by(step).copied().collect();
+    let mut best = (0, 0);
+    let mut best_size = usize::MAX;
+    for exponent in 0..=MAX_EXPONENT {
+        for factor in 0..=exponent {
+            let size = estimate(&sample, exponent, factor);
+            if size < best_size {
+                best = (exponent, factor);
+                best_size = size;
+            }
+        }
+    }
+    best
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "alp pages hold at most u32::MAX values",
+    )
+}
+
+pub fn alp_encode(src: &[f64], dst: &mut Vec<u8>) -> io::Result<()> {
+    let count = u32::try_from(src.len()).map_err(|_| too_large())?;
+    let (exponent, factor) = choose(src);
+
+    let encoded: Vec<Option<i64>> = src
+        .iter()
+        .map(|&value| encode_one(value, exponent, factor))
+        .collect();
+    let fill = encoded.iter().flatten().next().copied().unwrap_or(0);
+    let ints: Vec<i64> = encoded.iter().map(|n| n.unwrap_or(fill)).collect();
+    let min = ints.iter().copied().min().unwrap_or(0);
+    let max = ints.iter().copied().max().unwrap_or(0);
+    let width = bit_width(max.wrapping_sub(min) as u64);
+
+    let exceptions: Vec<(u32, u64)> = encoded
+        .iter()
+        .zip(src)
+        .enumerate()
+        .filter(|(_, (n, _))| n.is_none())
+        .map(|(i, (_, value))| (i as u32, value.to_bits()))
+        .collect();
+
+    dst.extend_from_slice(&count.to_le_bytes());
+    dst.extend_from_slice(&[exponent as u8, factor as u8, width as u8]);
+    dst.extend_from_slice(&min.to_le_bytes());
+    dst.extend_from_slice(&(exceptions.len() as u32).to_le_bytes());
+    for (position, bits) in exceptions {
+        dst.extend_from_slice(&position.to_le_bytes());
+        dst.extend_from_slice(&bits.to_le_bytes());
+    }
+
+    if width > 0 {
+        let mut writer = Writer::new(dst);
+        for n in ints {
+            writer.write_bits(n.wrapping_sub(min) as u64 as u128, width as usize)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+pub fn alp_decode(bytes: &[u8]) -> io::Result<Vec<f64>> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "alp page is truncated");
+    let header = bytes.get(..HEADER_BYTES).ok_or_else(truncated)?;
+    let count = LittleEndian::read_u32(header) as usize;
+    let (exponent, factor, width) = (header[4] as usize, header[5] as usize, header[6] as usize);
+    let min = LittleEndian::read_i64(&header[7..]);
+    let exception_count = LittleEndian::read_u32(&header[15..]) as usize;
+    if exponent > MAX_EXPONENT || factor > exponent || width > 64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "alp page has exponent {}, factor {} and bit width {}",
+                exponent, factor, width
+            ),
+        ));
+    }
+
+    let exceptions = exception_count
+        .checked_mul(EXCEPTION_BYTES)
+        .and_then(|len| bytes.get(HEADER_BYTES..HEADER_BYTES + len))
+        .ok_or_else(truncated)?;
+    let packed = &bytes[HEADER_BYTES + exceptions.len()..];
+    // a count the packed bits cannot hold is rejected before allocating for
+    // it; with a width of 0 every value is the minimum and only the header
+    // is needed
+    let packed_bits = count.checked_mul(width).ok_or_else(truncated)?;
+    if packed.len() < packed_bits.div_ceil(8) {
+        return Err(truncated());
+    }
+
+    let mut values = Vec::with_capacity(count);
+    if width > 0 {
+        let mut reader = Reader::new(packed);
+        for _ in 0..count {
+            let n = min.wrapping_add(reader.read_bits(width)? as u64 as i64);
+            values.push(decode_one(n, exponent, factor));
+        }
+    } else {
+        values.resize(count, decode_one(min, exponent, factor));
+    }
+
+    for exception in exceptions.chunks_exact(EXCEPTION_BYTES) {
+        let position = LittleEndian::read_u32(exception) as usize;
+        let value = values.get_mut(position).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "alp exception at {} is outside the page of {} values",
+                    position, count
+                ),
+            )
+        })?;
+        *value = f64::from_bits(LittleEndian::read_u64(&exception[4..]));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[f64]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        alp_encode(values, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        alp_decode(&encode(values)).unwrap()
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    fn exception_count(bytes: &[u8]) -> u32 {
+        LittleEndian::read_u32(&bytes[15..])
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    // prices with two decimals
+    fn decimals(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|i| (10000 + i * 37 % 997) as f64 / 100.0)
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_bits_eq(&[-1.25], &round_trip(&[-1.25]));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn encodes_decimals_without_exceptions() {
+        let values = decimals(1000);
+        let bytes = encode(&values);
+        assert_eq!(exception_count(&bytes), 0);
+        assert_bits_eq(&values, &alp_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn stores_what_does_not_come_back_as_exceptions() {
+        let mut values = decimals(1000);
+        values[3] = f64::NAN;
+        values[500] = -0.0;
+        values[998] = std::f64::consts::PI;
+        let bytes = encode(&values);
+        assert_eq!(exception_count(&bytes), 3);
+        assert_bits_eq(&values, &alp_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn packs_a_constant_page_in_the_header() {
+        let values = [12.5; 100];
+        let bytes = encode(&values);
+        assert_eq!(bytes[6], 0);
+        assert_eq!(bytes.len(), HEADER_BYTES);
+        assert_bits_eq(&values, &alp_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn rejects_counts_the_packed_bits_cannot_hold() {
+        let mut bytes = encode(&decimals(100));
+        bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = alp_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_exceptions_outside_the_page() {
+        let mut values = decimals(10);
+        values[9] = f64::NAN;
+        let mut bytes = encode(&values);
+        bytes[HEADER_BYTES..HEADER_BYTES + 4].copy_from_slice(&10_u32.to_le_bytes());
+        let err = alp_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let mut values = decimals(20);
+        values[7] = f64::NAN;
+        let bytes = encode(&values);
+        for len in 0..bytes.len() {
+            let err = alp_decode(&bytes[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::chunk::Chunking;
+use crate::codec::FloatCodec;
+use crate::stats::Summary;
+use crate::verify::{self, ErrorStats};
+
+/// Page size in bytes of the default chunking.
+pub const PAGE_BYTES: u32 = 65535;
+
+/// How often each page is encoded and decoded.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// Untimed runs per page before measuring, to warm caches and allocators.
+    pub warmup: usize,
+    /// Timed runs per page.
+    pub repetitions: usize,
+    /// Absolute error up to which a lossy codec's value counts as acceptable.
+    pub tolerance: f64,
+}
+
+/// The timed phases of a round trip, see `FloatCodec`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Prepare,
+    Encode,
+    Decode,
+    Finalize,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::Prepare,
+        Phase::Encode,
+        Phase::Decode,
+        Phase::Finalize,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Prepare => "prepare",
+            Phase::Encode => "encode",
+            Phase::Decode => "decode",
+            Phase::Finalize => "finalize",
+        }
+    }
+}
+
+/// Measurements for a single page of data.
+#[derive(Clone, Debug)]
+pub struct PageResult {
+    pub num_values: usize,
+    pub compressed_bytes: usize,
+    /// One entry per repetition for each phase, indexed like `Phase::ALL`.
+    pub times: [Vec<Duration>; 4],
+    /// Only measured for lossy codecs.
+    pub errors: Option<ErrorStats>,
+}
+
+impl PageResult {
+    pub fn uncompressed_bytes(&self) -> usize {
+        self.num_values * 8
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_bytes() as f64 / self.compressed_bytes as f64
+    }
+
+    pub fn times(&self, phase: Phase) -> &[Duration] {
+        &self.times[phase as usize]
+    }
+
+    pub fn median_time(&self, phase: Phase) -> Duration {
+        median(self.times(phase))
+    }
+}
+
+fn median(times: &[Duration]) -> Duration {
+    let secs: Vec<f64> = times.iter().map(Duration::as_secs_f64).collect();
+    Duration::from_secs_f64(Summary::of(&secs).median)
+}
+
+/// Timing of a whole dataset across repetitions, with the resulting throughput.
+#[derive(Clone, Debug)]
+pub struct Timing {
+    /// Seconds to process every page once, over all repetitions.
+    pub total_secs: Summary,
+    /// Uncompressed megabytes (10^6 bytes) per second at the median time.
+    pub mb_per_sec: f64,
+    /// Values per second at the median time.
+    pub values_per_sec: f64,
+}
+
+/// Measurements for one codec configuration over a whole dataset.
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub codec: String,
+    pub params: Vec<(&'static str, String)>,
+    pub chunking: Chunking,
+    pub options: Options,
+    pub pages: Vec<PageResult>,
+}
+
+impl BenchResult {
+    pub fn num_values(&self) -> usize {
+        self.pages.iter().map(|p| p.num_values).sum()
+    }
+
+    pub fn uncompressed_bytes(&self) -> usize {
+        self.pages.iter().map(|p| p.uncompressed_bytes()).sum()
+    }
+
+    pub fn compressed_bytes(&self) -> usize {
+        self.pages.iter().map(|p| p.compressed_bytes).sum()
+    }
+
+    /// Total bytes in over total bytes out. Unlike the mean of the page ratios,
+    /// this does not overweight the short final page.
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_bytes() as f64 / self.compressed_bytes() as f64
+    }
+
+    pub fn bits_per_value(&self) -> f64 {
+        (self.compressed_bytes() * 8) as f64 / self.num_values() as f64
+    }
+
+    /// Errors over the whole dataset, `None` for lossless codecs.
+    pub fn errors(&self) -> Option<ErrorStats> {
+        let mut pages = self.pages.iter().filter_map(|p| p.errors);
+        let mut errors = pages.next()?;
+        for page in pages {
+            errors.merge(&page);
+        }
+        Some(errors)
+    }
+
+    /// Distribution of the number of values per page.
+    pub fn page_sizes(&self) -> Summary {
+        let sizes: Vec<f64> = self.pages.iter().map(|p| p.num_values as f64).collect();
+        Summary::of(&sizes)
+    }
+
+    pub fn page_ratios(&self) -> Summary {
+        let ratios: Vec<f64> = self.pages.iter().map(|p| p.compression_ratio()).collect();
+        Summary::of(&ratios)
+    }
+
+    // sums the pages of each repetition, then summarizes across repetitions
+    pub fn timing(&self, phase: Phase) -> Timing {
+        let totals: Vec<f64> = (0..self.options.repetitions)
+            .map(|rep| {
+                self.pages
+                    .iter()
+                    .map(|p| p.times(phase)[rep].as_secs_f64())
+                    .sum()
+            })
+            .collect();
+        let total_secs = Summary::of(&totals);
+        Timing {
+            mb_per_sec: self.uncompressed_bytes() as f64 / 1e6 / total_secs.median,
+            values_per_sec: self.num_values() as f64 / total_secs.median,
+            total_secs,
+        }
+    }
+}
+
+/// Benchmarks `codec` on `vec_total`, split into pages by `chunking`. `timestamps`
+/// holds one per value for inputs that have them. Fails if a page of a lossless
+/// codec does not decode to exactly the values it was encoded from; for lossy
+/// codecs the error is measured instead.
+pub fn run(
+    codec: &dyn FloatCodec,
+    vec_total: &[f64],
+    timestamps: Option<&[u64]>,
+    chunking: Chunking,
+    options: &Options,
+) -> io::Result<BenchResult> {
+    let mut pages = Vec::new();
+
+    // loop that benchmarks the algorithm on each chunk of data
+    for range in chunking.split(codec, vec_total)? {
+        let offset = range.start;
+        let page_timestamps = timestamps.map(|timestamps| &timestamps[range.clone()]);
+        let vec = &vec_total[range];
+        let prepare = || match page_timestamps {
+            Some(page_timestamps) => codec.prepare_timestamped(page_timestamps, vec),
+            None => codec.prepare(vec),
+        };
+        for _ in 0..options.warmup {
+            let bytes = codec.encode_prepared(&prepare()?)?;
+            codec.decode(&bytes)?;
+        }
+
+        let mut compressed_num_bytes = 0;
+        let mut errors = None;
+        let mut times: [Vec<Duration>; 4] = Default::default();
+        for _ in 0..options.repetitions {
+            // each phase gets its own timer so that conversions done in prepare or
+            // finalize are never counted as encoding or decoding
+            let timer = Instant::now();
+            let prepared = prepare()?;
+            times[Phase::Prepare as usize].push(timer.elapsed());
+
+            let timer = Instant::now();
+            let bytes = codec.encode_prepared(&prepared)?;
+            times[Phase::Encode as usize].push(timer.elapsed());
+            compressed_num_bytes = bytes.len();
+            drop(prepared);
+
+            let timer = Instant::now();
+            let decoded = codec.decode_raw(&bytes)?;
+            times[Phase::Decode as usize].push(timer.elapsed());
+            if let Some(page_timestamps) = page_timestamps {
+                verify::timestamps(&codec.name(), offset, page_timestamps, &decoded)?;
+            }
+
+            let timer = Instant::now();
+            let decoded = codec.finalize(decoded)?;
+            times[Phase::Finalize as usize].push(timer.elapsed());
+
+            if codec.lossy() {
+                verify::same_len(&codec.name(), offset, vec, &decoded)?;
+                errors = Some(ErrorStats::of(options.tolerance, vec, &decoded));
+            } else {
+                // a codec that loses data must not get a compression ratio published
+                verify::bit_exact(&codec.name(), offset, vec, &decoded)?;
+            }
+        }
+
+        pages.push(PageResult {
+            num_values: vec.len(),
+            compressed_bytes: compressed_num_bytes,
+            times,
+            errors,
+        });
+    }
+
+    println!("{} compression done", codec.name());
+    Ok(BenchResult {
+        codec: codec.name(),
+        params: codec.params(),
+        chunking,
+        options: *options,
+        pages,
+    })
+}
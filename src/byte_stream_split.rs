@@ -0,0 +1,131 @@
+//! Parquet's BYTE_STREAM_SPLIT encoding.
+//!
+//! Byte k of every value goes to stream k, and the streams are concatenated.
+//! The bytes at one position of similar doubles are similar too (the sign and
+//! exponent bytes hardly change, the low mantissa bytes are close to random),
+//! but a byte-oriented compressor sees them 8 bytes apart. After splitting,
+//! the predictable bytes form long runs and the random ones no longer break
+//! them up.
+
+use std::io;
+
+/// Splits `bytes`, a sequence of `width` byte values, into one stream per
+/// byte position.
+pub fn split_streams(bytes: &[u8], width: usize) -> Vec<u8> {
+    let count = bytes.len() / width;
+    let mut streams = vec![0_u8; count * width];
+    for (i, value) in bytes.chunks_exact(width).enumerate() {
+        for (k, &byte) in value.iter().enumerate() {
+            streams[k * count + i] = byte;
+        }
+    }
+    streams
+}
+
+/// Inverse of `split_streams`.
+pub fn join_streams(streams: &[u8], width: usize) -> io::Result<Vec<u8>> {
+    if !streams.len().is_multiple_of(width) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} bytes do not split into streams of {} byte values",
+                streams.len(),
+                width
+            ),
+        ));
+    }
+    let count = streams.len() / width;
+    let mut bytes = vec![0_u8; streams.len()];
+    for (k, stream) in streams.chunks_exact(count.max(1)).enumerate() {
+        for (i, &byte) in stream.iter().enumerate() {
+            bytes[i * width + k] = byte;
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{bytes_to_f64s, f64s_to_bytes};
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        let streams = split_streams(&f64s_to_bytes(values), 8);
+        bytes_to_f64s(&join_streams(&streams, 8).unwrap())
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert!(split_streams(&[], 8).is_empty());
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_bits_eq(&[-1.25], &round_trip(&[-1.25]));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn writes_byte_k_of_every_value_to_stream_k() {
+        let bytes = [0, 1, 2, 10, 11, 12, 20, 21, 22, 30, 31, 32];
+        let streams = split_streams(&bytes, 3);
+        assert_eq!(streams, [0, 10, 20, 30, 1, 11, 21, 31, 2, 12, 22, 32]);
+        assert_eq!(join_streams(&streams, 3).unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_streams_that_are_not_whole_values() {
+        let err = join_streams(&[0; 12], 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
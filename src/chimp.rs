@@ -0,0 +1,335 @@
+//! Chimp and Chimp128, the XOR encoders from "Chimp: Efficient Lossless
+//! Floating Point Compression for Time Series Databases" (Liakos et al., 2022).
+//!
+//! Like Gorilla, every value is stored as its XOR with an earlier value, but the
+//! control bits are chosen for XORs with few trailing zeros:
+//!
+//! * `00`: the XOR is 0
+//! * `01`: 3 bits of rounded leading zeros, 6 bits of significant bits, then the
+//!   significant bits, used when the XOR has more trailing zeros than a threshold
+//! * `10`: the XOR has the same rounded leading zeros as the previous one, the
+//!   remaining bits follow
+//! * `11`: 3 bits of rounded leading zeros, then the remaining bits
+//!
+//! Chimp128 XORs with the best of the previous 128 values rather than the
+//! previous one: for `00` and `01` the 7 bit position of that value in a ring
+//! buffer follows the control bits. It finds a candidate through a table of the
+//! last position of every pattern of low bits.
+//!
+//! The stream starts with the number of values in 32 bits instead of ending
+//! with a NaN as the paper's code does, so that NaNs round trip too.
+
+use bit_streamer::{Reader, Writer};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+// leading zeros are rounded down to one of these, stored as their 3 bit index
+const LEADING_ROUND: [u32; 8] = [0, 8, 12, 16, 18, 20, 22, 24];
+
+fn leading_class(leading_zeros: u32) -> usize {
+    LEADING_ROUND
+        .iter()
+        .rposition(|&round| round <= leading_zeros)
+        .unwrap_or(0)
+}
+
+// never equal to a rounded leading zero count, so `10` cannot follow `00` or `01`
+const NO_LEADING_ZEROS: u32 = 65;
+
+/// Chimp encoder over the last `1 << log2_previous` values; 0 gives plain Chimp,
+/// 7 gives Chimp128.
+pub struct ChimpEncoder<W: Write> {
+    writer: Writer<W>,
+    log2_previous: u32,
+    threshold: u32,
+    /// Ring buffer of the previous values.
+    previous: Vec<u64>,
+    /// Position of the last value for each pattern of low bits.
+    indices: Vec<usize>,
+    count: usize,
+    stored_leading_zeros: u32,
+}
+
+impl<W: Write> ChimpEncoder<W> {
+    pub fn new(dst: W, log2_previous: u32) -> Self {
+        let threshold = 6 + log2_previous;
+        let indices = if log2_previous > 0 {
+            vec![0; 1 << (threshold + 1)]
+        } else {
+            Vec::new()
+        };
+        ChimpEncoder {
+            writer: Writer::new(dst),
+            log2_previous,
+            threshold,
+            previous: vec![0; 1 << log2_previous],
+            indices,
+            count: 0,
+            stored_leading_zeros: NO_LEADING_ZEROS,
+        }
+    }
+
+    /// Encodes `values` as a whole stream, including the value count.
+    pub fn encode(mut self, values: &[f64]) -> io::Result<()> {
+        let count = u32::try_from(values.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chimp streams hold at most u32::MAX values",
+            )
+        })?;
+        self.writer.write_bits(count as u128, 32)?;
+        for &value in values {
+            self.push(value.to_bits())?;
+        }
+        self.writer.flush()
+    }
+
+    fn push(&mut self, value: u64) -> io::Result<()> {
+        let slots = self.previous.len();
+        if self.count == 0 {
+            // First write the first value in full
+            self.writer.write_bits(value as u128, 64)?;
+            self.remember(value);
+            return Ok(());
+        }
+
+        // the previous value, unless one with the same low bits is recent enough
+        // and leaves more trailing zeros than the threshold
+        let mut position = (self.count - 1) % slots;
+        if !self.indices.is_empty() {
+            let key = value as usize & (self.indices.len() - 1);
+            let candidate = self.indices[key];
+            if self.count - candidate <= slots {
+                let xor = value ^ self.previous[candidate % slots];
+                if xor.trailing_zeros() > self.threshold {
+                    position = candidate % slots;
+                }
+            }
+        }
+        let xor = value ^ self.previous[position];
+
+        if xor == 0 {
+            self.writer.write_bits(0b00, 2)?;
+            self.write_index(position)?;
+            self.stored_leading_zeros = NO_LEADING_ZEROS;
+        } else {
+            let class = leading_class(xor.leading_zeros());
+            let leading_zeros = LEADING_ROUND[class];
+            let trailing_zeros = xor.trailing_zeros();
+            if trailing_zeros > self.threshold {
+                let significant_bits = 64 - leading_zeros - trailing_zeros;
+                self.writer.write_bits(0b01, 2)?;
+                self.write_index(position)?;
+                self.writer.write_bits(class as u128, 3)?;
+                self.writer.write_bits(significant_bits as u128, 6)?;
+                self.writer
+                    .write_bits((xor >> trailing_zeros) as u128, significant_bits as usize)?;
+                self.stored_leading_zeros = NO_LEADING_ZEROS;
+            } else if leading_zeros == self.stored_leading_zeros {
+                self.writer.write_bits(0b10, 2)?;
+                self.writer
+                    .write_bits(xor as u128, (64 - leading_zeros) as usize)?;
+            } else {
+                self.stored_leading_zeros = leading_zeros;
+                self.writer.write_bits(0b11, 2)?;
+                self.writer.write_bits(class as u128, 3)?;
+                self.writer
+                    .write_bits(xor as u128, (64 - leading_zeros) as usize)?;
+            }
+        }
+        self.remember(value);
+        Ok(())
+    }
+
+    // plain Chimp has no index
+    fn write_index(&mut self, position: usize) -> io::Result<()> {
+        if self.log2_previous == 0 {
+            return Ok(());
+        }
+        self.writer
+            .write_bits(position as u128, self.log2_previous as usize)
+    }
+
+    fn remember(&mut self, value: u64) {
+        let slots = self.previous.len();
+        self.previous[self.count % slots] = value;
+        if !self.indices.is_empty() {
+            let key = value as usize & (self.indices.len() - 1);
+            self.indices[key] = self.count;
+        }
+        self.count += 1;
+    }
+}
+
+fn read_index<R: Read>(reader: &mut Reader<R>, bits: usize) -> io::Result<usize> {
+    if bits == 0 {
+        return Ok(0);
+    }
+    Ok(reader.read_bits(bits)? as usize)
+}
+
+/// Decodes a stream written by `ChimpEncoder` with the same `log2_previous`.
+pub fn chimp_decode<R: Read>(src: R, log2_previous: u32) -> io::Result<Vec<f64>> {
+    let mut reader = Reader::new(src);
+    let index_bits = log2_previous as usize;
+    let count = reader.read_bits(32)? as usize;
+    let slots = 1 << log2_previous;
+    let mut previous = vec![0_u64; slots];
+    let mut stored_leading_zeros = 0;
+    let mut values = Vec::new();
+
+    for i in 0..count {
+        let value = if i == 0 {
+            reader.read_bits(64)? as u64
+        } else {
+            let last = previous[(i - 1) % slots];
+            match reader.read_bits(2)? {
+                0b00 => previous[read_index(&mut reader, index_bits)?],
+                0b01 => {
+                    let position = read_index(&mut reader, index_bits)?;
+                    let leading_zeros = LEADING_ROUND[reader.read_bits(3)? as usize];
+                    let significant_bits = match reader.read_bits(6)? as u32 {
+                        0 => 64,
+                        bits => bits,
+                    };
+                    if leading_zeros + significant_bits > 64 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "chimp value {} has {} leading zeros and {} significant bits",
+                                i, leading_zeros, significant_bits
+                            ),
+                        ));
+                    }
+                    let trailing_zeros = 64 - leading_zeros - significant_bits;
+                    let bits = reader.read_bits(significant_bits as usize)? as u64;
+                    previous[position] ^ (bits << trailing_zeros)
+                }
+                0b10 => last ^ reader.read_bits((64 - stored_leading_zeros) as usize)? as u64,
+                _ => {
+                    stored_leading_zeros = LEADING_ROUND[reader.read_bits(3)? as usize];
+                    last ^ reader.read_bits((64 - stored_leading_zeros) as usize)? as u64
+                }
+            }
+        };
+        previous[i % slots] = value;
+        values.push(f64::from_bits(value));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(values: &[f64], log2_previous: u32) -> Vec<f64> {
+        let mut bytes = Vec::new();
+        ChimpEncoder::new(&mut bytes, log2_previous)
+            .encode(values)
+            .unwrap();
+        chimp_decode(&bytes[..], log2_previous).unwrap()
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        for log2_previous in [0, 7] {
+            assert!(round_trip(&[], log2_previous).is_empty());
+        }
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        for log2_previous in [0, 7] {
+            assert_bits_eq(&[-1.25], &round_trip(&[-1.25], log2_previous));
+        }
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        for log2_previous in [0, 7] {
+            assert_bits_eq(&values, &round_trip(&values, log2_previous));
+        }
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        for log2_previous in [0, 7] {
+            assert_bits_eq(&values, &round_trip(&values, log2_previous));
+        }
+    }
+
+    #[test]
+    fn round_trips_values_repeating_within_and_beyond_the_ring_buffer() {
+        let mut values = Vec::new();
+        for period in [5, 100, 200] {
+            values.extend((0..600).map(|i| ((i % period) as f64 * 0.37).sin() * 100.0));
+        }
+        for log2_previous in [0, 7] {
+            assert_bits_eq(&values, &round_trip(&values, log2_previous));
+        }
+    }
+
+    #[test]
+    fn chimp128_finds_earlier_values() {
+        let values: Vec<f64> = (0..1000).map(|i| (i % 50) as f64 * 1.1).collect();
+        let size = |log2_previous| {
+            let mut bytes = Vec::new();
+            ChimpEncoder::new(&mut bytes, log2_previous)
+                .encode(&values)
+                .unwrap();
+            bytes.len()
+        };
+        assert!(size(7) < size(0));
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let values = random_values(20);
+        let mut bytes = Vec::new();
+        ChimpEncoder::new(&mut bytes, 7).encode(&values).unwrap();
+        for len in 0..bytes.len() {
+            let err = chimp_decode(&bytes[..len], 7).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! How the input is split into pages before it is handed to a codec.
+//!
+//! A chunking is given as a spec string:
+//!
+//! * `values=N`: N values per page
+//! * `bytes=N`: as many values as fit into N uncompressed bytes
+//! * `whole`: the whole input as a single page
+//! * `target=N`: the longest pages that still compress to at most N bytes
+//!
+//! Sizes may be alternatives separated by `/` (`values=1024/8192/65536`), in
+//! which case the spec expands to one chunking per size.
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+use crate::codec::FloatCodec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chunking {
+    Values(usize),
+    Bytes(usize),
+    Whole,
+    /// Compressed bytes per page.
+    Target(usize),
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Chunking::Bytes(crate::bench::PAGE_BYTES as usize)
+    }
+}
+
+impl fmt::Display for Chunking {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chunking::Values(n) => write!(f, "values={}", n),
+            Chunking::Bytes(n) => write!(f, "bytes={}", n),
+            Chunking::Whole => write!(f, "whole"),
+            Chunking::Target(n) => write!(f, "target={}", n),
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Parses a chunking spec, expanding `/` alternatives.
+pub fn parse(spec: &str) -> io::Result<Vec<Chunking>> {
+    let spec = spec.trim();
+    if spec == "whole" {
+        return Ok(vec![Chunking::Whole]);
+    }
+    let (kind, sizes) = spec.split_once('=').ok_or_else(|| {
+        invalid(format!(
+            "chunking {:?} is not whole, values=N, bytes=N or target=N",
+            spec
+        ))
+    })?;
+    let make: fn(usize) -> Chunking = match kind.trim() {
+        "values" => Chunking::Values,
+        "bytes" => Chunking::Bytes,
+        "target" => Chunking::Target,
+        other => return Err(invalid(format!("unknown chunking {:?}", other))),
+    };
+    sizes
+        .split('/')
+        .map(|size| match size.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(make(n)),
+            _ => Err(invalid(format!(
+                "chunking size {:?} is not a positive integer",
+                size
+            ))),
+        })
+        .collect()
+}
+
+/// Parses a chunking spec that must describe exactly one chunking.
+pub fn parse_one(spec: &str) -> io::Result<Chunking> {
+    match parse(spec)?.as_slice() {
+        [chunking] => Ok(*chunking),
+        _ => Err(invalid(format!(
+            "{:?} describes more than one chunking",
+            spec
+        ))),
+    }
+}
+
+impl Chunking {
+    /// The index ranges of the pages `values` is split into. `codec` is only
+    /// used by `Target`, which compresses candidate pages to size them.
+    pub fn split(&self, codec: &dyn FloatCodec, values: &[f64]) -> io::Result<Vec<Range<usize>>> {
+        let fixed = |n: usize| {
+            (0..values.len())
+                .step_by(n)
+                .map(|start| start..values.len().min(start + n))
+                .collect()
+        };
+        match *self {
+            Chunking::Values(n) => Ok(fixed(n)),
+            Chunking::Bytes(n) => Ok(fixed((n / 8).max(1))),
+            Chunking::Whole if values.is_empty() => Ok(Vec::new()),
+            #[allow(clippy::single_range_in_vec_init)]
+            Chunking::Whole => Ok(vec![0..values.len()]),
+            Chunking::Target(target) => {
+                let mut pages = Vec::new();
+                let mut start = 0;
+                while start < values.len() {
+                    let len = longest_page(codec, &values[start..], target)?;
+                    pages.push(start..start + len);
+                    start += len;
+                }
+                Ok(pages)
+            }
+        }
+    }
+}
+
+// number of leading values that compress to at most `target` bytes, found by
+// doubling and then bisecting; at least one value so that splitting progresses
+fn longest_page(codec: &dyn FloatCodec, values: &[f64], target: usize) -> io::Result<usize> {
+    let fits = |n: usize| -> io::Result<bool> { Ok(codec.encode(&values[..n])?.len() <= target) };
+
+    let mut fitting = 1;
+    let mut too_long = loop {
+        let n = (fitting * 2).min(values.len());
+        if n == fitting {
+            return Ok(fitting);
+        }
+        if !fits(n)? {
+            break n;
+        }
+        fitting = n;
+    };
+    while too_long - fitting > 1 {
+        let mid = fitting + (too_long - fitting) / 2;
+        if fits(mid)? {
+            fitting = mid;
+        } else {
+            too_long = mid;
+        }
+    }
+    Ok(fitting)
+}
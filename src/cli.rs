@@ -0,0 +1,108 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Benchmarks and runs floating point compression codecs.
+///
+/// Input files hold one float per line, like the files in `data/`.
+#[derive(Parser, Debug)]
+#[clap(version, about)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Benchmark codecs on one or more input files
+    Bench {
+        /// Input files with one float per line
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Column to benchmark for `.csv` inputs, which also provide timestamps
+        /// from their `Date` column
+        #[clap(long, default_value = "Close/Last")]
+        column: String,
+
+        /// Codec specs such as `zstd:level=-5..5` or `blosc:lz4,shuffle=bit`; a bare
+        /// codec name runs its default sweep and `baseline` runs the sisu page builder
+        #[clap(short, long = "codec")]
+        codecs: Vec<String>,
+
+        /// File with one codec spec per line, `#` starts a comment
+        #[clap(long)]
+        config: Option<PathBuf>,
+
+        /// How to split inputs into pages: `values=N`, `bytes=N`, `whole` or
+        /// `target=N` compressed bytes; sizes may be swept as `values=1024/8192/65536`.
+        /// Defaults to `bytes=65535`
+        #[clap(long)]
+        chunking: Vec<String>,
+
+        /// Directory for results; defaults to `results/<input file stem>`
+        #[clap(short, long)]
+        out_dir: Option<PathBuf>,
+
+        /// Untimed runs per page before measuring
+        #[clap(long, default_value_t = 1)]
+        warmup: usize,
+
+        /// Timed runs per page
+        #[clap(short, long, default_value_t = 5)]
+        repetitions: usize,
+
+        /// Absolute error a lossy codec may introduce for a value to count as
+        /// within tolerance; the default is half a cent
+        #[clap(long, default_value_t = 0.005)]
+        tolerance: f64,
+    },
+
+    /// Compress an input file page by page
+    Compress {
+        input: PathBuf,
+        output: PathBuf,
+
+        /// Codec spec such as `zstd:level=3`
+        #[clap(short, long)]
+        codec: String,
+
+        /// How to split the input into pages, see `bench --help`
+        #[clap(long)]
+        chunking: Option<String>,
+    },
+
+    /// Decompress a file written by `compress` back to one float per line
+    Decompress { input: PathBuf, output: PathBuf },
+
+    /// Print the codec, pages and sizes of a file written by `compress`
+    Inspect { input: PathBuf },
+
+    /// Train a zstd dictionary on sample pages of one or more input files, for
+    /// the `zstd_dict` codec
+    Train {
+        /// Input files with one float per line
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Dictionary file to write; defaults to
+        /// `results/<first input file stem>/<stem>.dict`
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Values per sample, ideally the page size the dictionary is used with;
+        /// zstd needs a handful of samples at least
+        #[clap(long, default_value_t = 256)]
+        sample_values: usize,
+
+        /// Largest dictionary size in bytes
+        #[clap(long, default_value_t = 4096)]
+        max_size: usize,
+
+        /// Byte stream split the samples, for `zstd_dict:split=true`
+        #[clap(long)]
+        split: bool,
+    },
+
+    /// List every codec with its parameters
+    List,
+}
@@ -0,0 +1,1071 @@
+use byteorder::{ByteOrder, LittleEndian};
+use lzzzz::{lz4, lz4_hc, lz4f};
+use std::borrow::Cow;
+use std::ffi::{c_void, CString};
+use std::io::{self, prelude::*};
+use std::os::raw::c_int;
+use tsz::decode::Error as TszError;
+use tsz::stream::{BufferedReader, BufferedWriter};
+use tsz::{DataPoint, Decode, Encode, StdDecoder, StdEncoder};
+
+use crate::alp::{alp_decode, alp_encode};
+use crate::byte_stream_split::{join_streams, split_streams};
+use crate::chimp::{chimp_decode, ChimpEncoder};
+use crate::decimal::{decimal_decode, decimal_encode};
+use crate::dictionary::Dictionary;
+use crate::elf::{elf_decode, elf_encode};
+use crate::fpc::{fpc_decode, fpc_encode};
+use crate::fpzip::{fpzip_compress, fpzip_decompress};
+use crate::gorilla::{gorilla_decode, gorilla_encode, GorillaTsDecoder, GorillaTsEncoder};
+use crate::patas::{patas_decode, patas_encode};
+use crate::pipeline::Transform;
+use crate::zfp::{zfp_compress, zfp_decompress, ZfpMode};
+
+/// Data handed between the phases of a codec.
+pub enum Stage<'a> {
+    Floats(Cow<'a, [f64]>),
+    /// Little endian bytes of the floats, for byte-oriented compressors.
+    Bytes(Vec<u8>),
+    /// (timestamp, value) pairs for tsz.
+    Points(Vec<DataPoint>),
+    /// The floats narrowed to f32, for libraries compressing single precision.
+    Singles(Vec<f32>),
+}
+
+impl<'a> Stage<'a> {
+    pub fn floats(&self) -> io::Result<&[f64]> {
+        match self {
+            Stage::Floats(floats) => Ok(floats),
+            _ => Err(wrong_stage("floats")),
+        }
+    }
+
+    pub fn bytes(&self) -> io::Result<&[u8]> {
+        match self {
+            Stage::Bytes(bytes) => Ok(bytes),
+            _ => Err(wrong_stage("bytes")),
+        }
+    }
+
+    pub fn points(&self) -> io::Result<&[DataPoint]> {
+        match self {
+            Stage::Points(points) => Ok(points),
+            _ => Err(wrong_stage("points")),
+        }
+    }
+
+    pub fn singles(&self) -> io::Result<&[f32]> {
+        match self {
+            Stage::Singles(singles) => Ok(singles),
+            _ => Err(wrong_stage("f32 floats")),
+        }
+    }
+
+    /// The same stage, copying borrowed floats.
+    pub fn into_owned(self) -> Stage<'static> {
+        match self {
+            Stage::Floats(floats) => Stage::Floats(Cow::Owned(floats.into_owned())),
+            Stage::Bytes(bytes) => Stage::Bytes(bytes),
+            Stage::Points(points) => Stage::Points(points),
+            Stage::Singles(singles) => Stage::Singles(singles),
+        }
+    }
+
+    pub fn into_floats(self) -> Vec<f64> {
+        match self {
+            Stage::Floats(floats) => floats.into_owned(),
+            Stage::Bytes(bytes) => bytes_to_f64s(&bytes),
+            Stage::Points(points) => points.iter().map(|dp| dp.get_value()).collect(),
+            Stage::Singles(singles) => singles.into_iter().map(f64::from).collect(),
+        }
+    }
+}
+
+fn wrong_stage(expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("codec expected {} from the previous phase", expected),
+    )
+}
+
+/// A codec that turns a page of floats into bytes and back.
+///
+/// Every codec benchmarked by this crate implements this trait so that a single
+/// driver (see `bench::run`) can split the data into pages, time each phase and
+/// write the results, regardless of which library does the work. The phases are
+///
+/// * prepare: convert the floats into whatever the library consumes
+/// * encode: the library call that produces the compressed bytes
+/// * decode: the library call that reads the compressed bytes
+/// * finalize: convert the library's output back into floats
+///
+/// and the driver times each of them separately, so that byte conversion or
+/// building `DataPoint`s is never counted as encoding for one codec but not another.
+pub trait FloatCodec {
+    /// Short name used for results files, e.g. `zstd` or `lzzzz_bytes`.
+    fn name(&self) -> String;
+
+    /// Parameters the codec was configured with, as `(name, value)` pairs.
+    fn params(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    /// Whether decoded values may differ from the input. Lossless codecs are
+    /// verified bit for bit, lossy ones get their error measured instead.
+    fn lossy(&self) -> bool {
+        false
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        Ok(Stage::Floats(Cow::Borrowed(src)))
+    }
+
+    /// Like `prepare`, with a timestamp for every value. Only codecs that store
+    /// timestamps use them, all others just prepare the values.
+    fn prepare_timestamped<'a>(&self, _times: &[u64], src: &'a [f64]) -> io::Result<Stage<'a>> {
+        self.prepare(src)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>>;
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>>;
+
+    fn finalize(&self, decoded: Stage) -> io::Result<Vec<f64>> {
+        Ok(decoded.into_floats())
+    }
+
+    /// Runs prepare and encode.
+    fn encode(&self, src: &[f64]) -> io::Result<Vec<u8>> {
+        let prepared = self.prepare(src)?;
+        self.encode_prepared(&prepared)
+    }
+
+    /// Runs decode and finalize.
+    fn decode(&self, bytes: &[u8]) -> io::Result<Vec<f64>> {
+        let decoded = self.decode_raw(bytes)?;
+        self.finalize(decoded)
+    }
+}
+
+// bytes for a byte-oriented compressor, byte stream split if `split` is set
+fn split_bytes_stage<'a>(src: &[f64], split: bool) -> io::Result<Stage<'a>> {
+    let bytes = f64s_to_bytes(src);
+    if split {
+        Ok(Stage::Bytes(split_streams(&bytes, 8)))
+    } else {
+        Ok(Stage::Bytes(bytes))
+    }
+}
+
+// inverse of `split_bytes_stage`
+fn finalize_split_bytes(decoded: Stage, split: bool) -> io::Result<Vec<f64>> {
+    if split {
+        Ok(bytes_to_f64s(&join_streams(decoded.bytes()?, 8)?))
+    } else {
+        Ok(decoded.into_floats())
+    }
+}
+
+pub(crate) fn to_io_error<E: std::fmt::Debug>(err: E) -> io::Error {
+    io::Error::other(format!("{:?}", err))
+}
+
+pub(crate) fn f64s_to_bytes(src: &[f64]) -> Vec<u8> {
+    let mut buf = vec![0_u8; src.len() * 8];
+    LittleEndian::write_f64_into(src, &mut buf);
+    buf
+}
+
+pub(crate) fn bytes_to_f64s(bytes: &[u8]) -> Vec<f64> {
+    let mut floats = vec![0_f64; bytes.len() / 8];
+    LittleEndian::read_f64_into(&bytes[..floats.len() * 8], &mut floats);
+    floats
+}
+
+// raw LZ4 blocks do not record their decompressed size, so we prefix it
+fn write_len_prefix(len: usize, dst: &mut Vec<u8>) {
+    let mut prefix = [0_u8; 4];
+    LittleEndian::write_u32(&mut prefix, len as u32);
+    dst.extend_from_slice(&prefix);
+}
+
+fn read_len_prefix(bytes: &[u8]) -> io::Result<(usize, &[u8])> {
+    if bytes.len() < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "missing length prefix",
+        ));
+    }
+    let (prefix, rest) = bytes.split_at(4);
+    Ok((LittleEndian::read_u32(prefix) as usize, rest))
+}
+
+// --------------------------------------------------------------------------------
+
+/// https://crates.io/crates/blosc
+///
+/// `blosc::Context` has no thread count, so pages are compressed and
+/// decompressed through the `_ctx` functions of `blosc_sys`, which take one
+/// and leave blosc's global state alone.
+pub struct Blosc {
+    pub compressor: blosc::Compressor,
+    pub shuffle: blosc::ShuffleMode,
+    /// 0 (no compression) to 9
+    pub clevel: u8,
+    /// Width in bytes of the elements blosc shuffles, 8 for the doubles
+    /// themselves, 4 to shuffle their halves.
+    pub typesize: usize,
+    /// Threads blosc splits a page's blocks across.
+    pub nthreads: u32,
+}
+
+const BLOSC_COMPRESSORS: [(&str, blosc::Compressor); 6] = [
+    ("blosclz", blosc::Compressor::BloscLZ),
+    ("lz4", blosc::Compressor::LZ4),
+    ("lz4hc", blosc::Compressor::LZ4HC),
+    ("snappy", blosc::Compressor::Snappy),
+    ("zlib", blosc::Compressor::Zlib),
+    ("zstd", blosc::Compressor::Zstd),
+];
+
+const BLOSC_SHUFFLES: [(&str, blosc::ShuffleMode); 3] = [
+    ("none", blosc::ShuffleMode::None),
+    ("byte", blosc::ShuffleMode::Byte),
+    ("bit", blosc::ShuffleMode::Bit),
+];
+
+impl Blosc {
+    pub fn compressor_from_name(name: &str) -> blosc::Compressor {
+        BLOSC_COMPRESSORS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c)
+            .unwrap_or(blosc::Compressor::Invalid)
+    }
+
+    pub fn shuffle_from_name(name: &str) -> blosc::ShuffleMode {
+        BLOSC_SHUFFLES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, s)| *s)
+            .unwrap_or(blosc::ShuffleMode::None)
+    }
+
+    fn compressor_name(&self) -> &'static str {
+        BLOSC_COMPRESSORS
+            .iter()
+            .find(|(_, c)| *c == self.compressor)
+            .map(|(n, _)| *n)
+            .unwrap_or("invalid")
+    }
+
+    // ShuffleMode has no PartialEq, so compare the discriminants
+    fn shuffle_name(&self) -> &'static str {
+        BLOSC_SHUFFLES
+            .iter()
+            .find(|(_, s)| *s as i32 == self.shuffle as i32)
+            .map(|(n, _)| *n)
+            .unwrap_or("none")
+    }
+}
+
+// bit shuffling with the LZ4 backend, as benchmarked in results/*/blosc_lz4_*
+impl Default for Blosc {
+    fn default() -> Self {
+        Blosc {
+            compressor: blosc::Compressor::LZ4,
+            shuffle: blosc::ShuffleMode::Bit,
+            clevel: 2,
+            typesize: 8,
+            nthreads: 1,
+        }
+    }
+}
+
+impl FloatCodec for Blosc {
+    // one name for every backend, so a sweep over them ends up in one table
+    fn name(&self) -> String {
+        "blosc".to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("compressor", self.compressor_name().to_string()),
+            ("shuffle", self.shuffle_name().to_string()),
+            ("clevel", self.clevel.to_string()),
+            ("typesize", self.typesize.to_string()),
+            ("nthreads", self.nthreads.to_string()),
+        ]
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let floats = prepared.floats()?;
+        let compressor = CString::new(self.compressor_name()).map_err(to_io_error)?;
+        let nbytes = floats.len() * 8;
+        let mut dst = vec![0_u8; nbytes + blosc_sys::BLOSC_MAX_OVERHEAD as usize];
+        let written = unsafe {
+            blosc_sys::blosc_compress_ctx(
+                self.clevel as c_int,
+                self.shuffle as c_int,
+                self.typesize,
+                nbytes,
+                floats.as_ptr() as *const c_void,
+                dst.as_mut_ptr() as *mut c_void,
+                dst.len(),
+                compressor.as_ptr(),
+                0,
+                self.nthreads as c_int,
+            )
+        };
+        if written <= 0 {
+            return Err(io::Error::other(format!(
+                "blosc compression failed with {}",
+                written
+            )));
+        }
+        dst.truncate(written as usize);
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        // Sadly, blosc trusts the sizes in the header until
+        // https://github.com/Blosc/c-blosc/issues/229 gets fixed, so check
+        // them against the page before decompressing
+        if bytes.len() < blosc_sys::BLOSC_MAX_OVERHEAD as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "blosc page is truncated",
+            ));
+        }
+        let (mut nbytes, mut cbytes, mut blocksize) = (0, 0, 0);
+        unsafe {
+            blosc_sys::blosc_cbuffer_sizes(
+                bytes.as_ptr() as *const c_void,
+                &mut nbytes,
+                &mut cbytes,
+                &mut blocksize,
+            )
+        };
+        if cbytes != bytes.len() || nbytes % 8 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "blosc header gives {} compressed bytes of {} for a page of {} bytes",
+                    cbytes,
+                    nbytes,
+                    bytes.len()
+                ),
+            ));
+        }
+        let mut floats = vec![0.0_f64; nbytes / 8];
+        let read = unsafe {
+            blosc_sys::blosc_decompress_ctx(
+                bytes.as_ptr() as *const c_void,
+                floats.as_mut_ptr() as *mut c_void,
+                nbytes,
+                self.nthreads as c_int,
+            )
+        };
+        if read < 0 || read as usize != nbytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("blosc decompression failed with {}", read),
+            ));
+        }
+        Ok(Stage::Floats(Cow::Owned(floats)))
+    }
+}
+
+/// https://crates.io/crates/q_compress
+/// https://github.com/mwlon/quantile-compression
+pub struct QCompress {
+    pub level: usize,
+}
+
+impl FloatCodec for QCompress {
+    fn name(&self) -> String {
+        "q_compress".to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        vec![("level", self.level.to_string())]
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        Ok(q_compress::auto_compress(prepared.floats()?, self.level))
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let floats = q_compress::auto_decompress::<f64>(bytes).map_err(to_io_error)?;
+        Ok(Stage::Floats(Cow::Owned(floats)))
+    }
+}
+
+/// https://docs.rs/zstd/latest/zstd/
+/// https://github.com/gyscos/zstd-rs
+///
+/// With `split`, the bytes are byte stream split before compressing, see
+/// `byte_stream_split.rs`.
+pub struct Zstd {
+    pub level: i32,
+    pub split: bool,
+    /// Trained dictionary to compress every page with, see `dictionary.rs`.
+    pub dictionary: Option<Dictionary>,
+}
+
+impl FloatCodec for Zstd {
+    fn name(&self) -> String {
+        match self.dictionary {
+            Some(_) => "zstd_dict".to_string(),
+            None => "zstd".to_string(),
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("level", self.level.to_string()),
+            ("split", self.split.to_string()),
+        ];
+        if let Some(dictionary) = &self.dictionary {
+            params.push(("dict", dictionary.path.clone()));
+        }
+        params
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        split_bytes_stage(src, self.split)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut encoder = match &self.dictionary {
+            Some(dictionary) => {
+                zstd::stream::Encoder::with_prepared_dictionary(Vec::new(), &dictionary.encoder)?
+            }
+            None => zstd::stream::Encoder::new(Vec::new(), self.level)?,
+        };
+        encoder.write_all(prepared.bytes()?)?;
+        encoder.finish()
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let mut decompressed_bytes = Vec::new();
+        match &self.dictionary {
+            Some(dictionary) => {
+                zstd::stream::Decoder::with_prepared_dictionary(bytes, &dictionary.decoder)?
+                    .read_to_end(&mut decompressed_bytes)?;
+            }
+            None => zstd::stream::copy_decode(bytes, &mut decompressed_bytes)?,
+        }
+        Ok(Stage::Bytes(decompressed_bytes))
+    }
+
+    fn finalize(&self, decoded: Stage) -> io::Result<Vec<f64>> {
+        finalize_split_bytes(decoded, self.split)
+    }
+}
+
+/// https://docs.rs/tsz/latest/tsz/
+/// https://github.com/jeromefroe/tsz-rs
+pub struct Tsz;
+
+// tsz and gorilla_ts compress (timestamp, value) pairs, so without real
+// timestamps every value gets the same one
+const TSZ_DEFAULT_TIMESTAMP: u64 = 1482892260;
+
+fn points_stage<'a>(times: Option<&[u64]>, src: &[f64]) -> io::Result<Stage<'a>> {
+    let datapoints = match times {
+        Some(times) => times
+            .iter()
+            .zip(src)
+            .map(|(time, num)| DataPoint::new(*time, *num))
+            .collect(),
+        None => src
+            .iter()
+            .map(|num| DataPoint::new(TSZ_DEFAULT_TIMESTAMP, *num))
+            .collect(),
+    };
+    Ok(Stage::Points(datapoints))
+}
+
+impl FloatCodec for Tsz {
+    fn name(&self) -> String {
+        "tsz".to_string()
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        points_stage(None, src)
+    }
+
+    fn prepare_timestamped<'a>(&self, times: &[u64], src: &'a [f64]) -> io::Result<Stage<'a>> {
+        points_stage(Some(times), src)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let datapoints = prepared.points()?;
+        // the header timestamp must not be after the first point
+        let start = datapoints
+            .first()
+            .map_or(TSZ_DEFAULT_TIMESTAMP, |dp| dp.get_time());
+        let w = BufferedWriter::new();
+        let mut encoder = StdEncoder::new(start, w);
+        for dp in datapoints {
+            encoder.encode(*dp);
+        }
+        Ok(encoder.close().into_vec())
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let r = BufferedReader::new(bytes.to_vec().into_boxed_slice());
+        let mut decoder = StdDecoder::new(r);
+        let mut datapoints = Vec::new();
+        loop {
+            match decoder.next() {
+                Ok(dp) => datapoints.push(dp),
+                Err(TszError::EndOfStream) => break,
+                Err(err) => return Err(to_io_error(err)),
+            }
+        }
+        Ok(Stage::Points(datapoints))
+    }
+}
+
+/// https://lib.rs/crates/snap
+///
+/// With `split`, the bytes are byte stream split before compressing.
+pub struct Snap {
+    pub split: bool,
+}
+
+impl FloatCodec for Snap {
+    fn name(&self) -> String {
+        "snap".to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        vec![("split", self.split.to_string())]
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        split_bytes_stage(src, self.split)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = snap::write::FrameEncoder::new(&mut compressed);
+            encoder.write_all(prepared.bytes()?)?;
+            encoder.flush()?;
+        }
+        Ok(compressed)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let mut decompressed = Vec::new();
+        snap::read::FrameDecoder::new(bytes).read_to_end(&mut decompressed)?;
+        Ok(Stage::Bytes(decompressed))
+    }
+
+    fn finalize(&self, decoded: Stage) -> io::Result<Vec<f64>> {
+        finalize_split_bytes(decoded, self.split)
+    }
+}
+
+/// Which of the lzzzz compressors to use.
+#[derive(Clone, Copy, Debug)]
+pub enum Lz4Mode {
+    /// LZ4 block format with an acceleration level
+    Block(i32),
+    /// LZ4_HC block format with a compression level
+    Hc(i32),
+    /// LZ4 frame format with default preferences
+    Frame,
+}
+
+/// https://crates.io/crates/lzzzz
+///
+/// With `bitshuffle` set, each page is first run through blosc's bit shuffle
+/// (with its LZ4 backend) and the resulting bytes are compressed again. With
+/// `split` set, the bytes are byte stream split instead; the two cannot be
+/// combined.
+pub struct Lz4 {
+    pub mode: Lz4Mode,
+    pub bitshuffle: bool,
+    pub split: bool,
+}
+
+impl Lz4 {
+    fn compress_bytes(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+        let mut comp_bytes = Vec::new();
+        match self.mode {
+            Lz4Mode::Block(acc) => {
+                write_len_prefix(src.len(), &mut comp_bytes);
+                lz4::compress_to_vec(src, &mut comp_bytes, acc).map_err(to_io_error)?;
+            }
+            Lz4Mode::Hc(level) => {
+                write_len_prefix(src.len(), &mut comp_bytes);
+                lz4_hc::compress_to_vec(src, &mut comp_bytes, level).map_err(to_io_error)?;
+            }
+            Lz4Mode::Frame => {
+                let prefs = lz4f::Preferences::default();
+                lz4f::compress_to_vec(src, &mut comp_bytes, &prefs).map_err(to_io_error)?;
+            }
+        }
+        Ok(comp_bytes)
+    }
+
+    fn decompress_bytes(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self.mode {
+            // LZ4 and LZ4_HC share the same block format
+            Lz4Mode::Block(_) | Lz4Mode::Hc(_) => {
+                let (len, comp_bytes) = read_len_prefix(bytes)?;
+                let mut decomp_bytes = vec![0_u8; len];
+                lz4::decompress(comp_bytes, &mut decomp_bytes).map_err(to_io_error)?;
+                Ok(decomp_bytes)
+            }
+            Lz4Mode::Frame => {
+                let mut decomp_bytes = Vec::new();
+                lz4f::decompress_to_vec(bytes, &mut decomp_bytes).map_err(to_io_error)?;
+                Ok(decomp_bytes)
+            }
+        }
+    }
+}
+
+impl FloatCodec for Lz4 {
+    fn name(&self) -> String {
+        if self.bitshuffle {
+            "lzzzz_bytes".to_string()
+        } else {
+            "lzzzz".to_string()
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = match self.mode {
+            Lz4Mode::Block(acc) => vec![
+                ("mode", "lz4".to_string()),
+                ("acceleration", acc.to_string()),
+            ],
+            Lz4Mode::Hc(level) => {
+                vec![("mode", "lz4_hc".to_string()), ("level", level.to_string())]
+            }
+            Lz4Mode::Frame => vec![("mode", "lz4f".to_string())],
+        };
+        params.push(("bitshuffle", self.bitshuffle.to_string()));
+        params.push(("split", self.split.to_string()));
+        params
+    }
+
+    // with bitshuffle, blosc works on the floats directly
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        if self.bitshuffle && self.split {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "lz4 cannot both bit shuffle with blosc and byte stream split",
+            ))
+        } else if self.bitshuffle {
+            Ok(Stage::Floats(Cow::Borrowed(src)))
+        } else {
+            split_bytes_stage(src, self.split)
+        }
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        if self.bitshuffle {
+            let vec_bytes: Vec<u8> = Blosc::default().encode_prepared(prepared)?;
+            self.compress_bytes(&vec_bytes)
+        } else {
+            self.compress_bytes(prepared.bytes()?)
+        }
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let decomp_bytes = self.decompress_bytes(bytes)?;
+        if self.bitshuffle {
+            Blosc::default().decode_raw(&decomp_bytes)
+        } else {
+            Ok(Stage::Bytes(decomp_bytes))
+        }
+    }
+
+    fn finalize(&self, decoded: Stage) -> io::Result<Vec<f64>> {
+        finalize_split_bytes(decoded, self.split)
+    }
+}
+
+/// The in-house XOR encoder from the Gorilla paper, see `gorilla.rs`.
+pub struct Gorilla;
+
+impl FloatCodec for Gorilla {
+    fn name(&self) -> String {
+        "gorilla".to_string()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        gorilla_encode(prepared.floats()?, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let mut decoded = Vec::new();
+        gorilla_decode(bytes, &mut decoded)?;
+        Ok(Stage::Floats(Cow::Owned(decoded)))
+    }
+}
+
+/// The full Gorilla time series format with delta-of-delta timestamps, see
+/// `GorillaTsEncoder`.
+pub struct GorillaTs;
+
+impl FloatCodec for GorillaTs {
+    fn name(&self) -> String {
+        "gorilla_ts".to_string()
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        points_stage(None, src)
+    }
+
+    fn prepare_timestamped<'a>(&self, times: &[u64], src: &'a [f64]) -> io::Result<Stage<'a>> {
+        points_stage(Some(times), src)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        let mut encoder = GorillaTsEncoder::new(&mut dst);
+        for dp in prepared.points()? {
+            encoder.push(dp.get_time(), dp.get_value())?;
+        }
+        encoder.finish()?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let mut datapoints = Vec::new();
+        for point in GorillaTsDecoder::new(bytes) {
+            let (time, value) = point?;
+            datapoints.push(DataPoint::new(time, value));
+        }
+        Ok(Stage::Points(datapoints))
+    }
+}
+
+/// Chimp over the last `1 << log2_previous` values, see `chimp.rs`.
+pub struct Chimp {
+    pub log2_previous: u32,
+}
+
+impl FloatCodec for Chimp {
+    fn name(&self) -> String {
+        match self.log2_previous {
+            0 => "chimp".to_string(),
+            log2 => format!("chimp{}", 1 << log2),
+        }
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        ChimpEncoder::new(&mut dst, self.log2_previous).encode(prepared.floats()?)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        let decoded = chimp_decode(bytes, self.log2_previous)?;
+        Ok(Stage::Floats(Cow::Owned(decoded)))
+    }
+}
+
+/// Byte-aligned Chimp128 from DuckDB, see `patas.rs`.
+pub struct Patas;
+
+impl FloatCodec for Patas {
+    fn name(&self) -> String {
+        "patas".to_string()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        patas_encode(prepared.floats()?, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(patas_decode(bytes)?)))
+    }
+}
+
+/// Chimp on values with their decimal noise erased, see `elf.rs`.
+pub struct Elf;
+
+impl FloatCodec for Elf {
+    fn name(&self) -> String {
+        "elf".to_string()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        elf_encode(prepared.floats()?, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(elf_decode(bytes)?)))
+    }
+}
+
+/// Decimal values scaled to integers and bit-packed, see `alp.rs`.
+pub struct Alp;
+
+impl FloatCodec for Alp {
+    fn name(&self) -> String {
+        "alp".to_string()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        alp_encode(prepared.floats()?, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(alp_decode(bytes)?)))
+    }
+}
+
+/// Decimal values as delta encoded integers at the page's scale, see
+/// `decimal.rs`.
+pub struct DecimalScaled;
+
+impl FloatCodec for DecimalScaled {
+    fn name(&self) -> String {
+        "decimal".to_string()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        decimal_encode(prepared.floats()?, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(decimal_decode(bytes)?)))
+    }
+}
+
+/// FPC with FCM and DFCM tables of `1 << level` entries, see `fpc.rs`.
+pub struct Fpc {
+    pub level: u32,
+}
+
+impl FloatCodec for Fpc {
+    fn name(&self) -> String {
+        "fpc".to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        vec![("level", self.level.to_string())]
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        fpc_encode(prepared.floats()?, self.level, &mut dst)?;
+        Ok(dst)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(fpc_decode(bytes)?)))
+    }
+}
+
+/// https://zfp.io, through the wrappers in `zfp.rs`. Only the reversible
+/// mode is lossless.
+pub struct Zfp {
+    pub mode: ZfpMode,
+}
+
+impl FloatCodec for Zfp {
+    fn name(&self) -> String {
+        match self.mode {
+            ZfpMode::Reversible => "zfp",
+            ZfpMode::FixedRate(_) => "zfp_rate",
+            ZfpMode::FixedPrecision(_) => "zfp_precision",
+            ZfpMode::FixedAccuracy(_) => "zfp_accuracy",
+        }
+        .to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        match self.mode {
+            ZfpMode::Reversible => Vec::new(),
+            ZfpMode::FixedRate(rate) => vec![("rate", rate.to_string())],
+            ZfpMode::FixedPrecision(precision) => vec![("precision", precision.to_string())],
+            // the spec gives the tolerance as 10^-digits
+            ZfpMode::FixedAccuracy(tolerance) => {
+                vec![("digits", ((-tolerance.log10()).round() as i32).to_string())]
+            }
+        }
+    }
+
+    fn lossy(&self) -> bool {
+        self.mode.lossy()
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        zfp_compress(prepared.floats()?, self.mode)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        Ok(Stage::Floats(Cow::Owned(zfp_decompress(bytes, self.mode)?)))
+    }
+}
+
+/// https://computing.llnl.gov/projects/fpzip, through the wrappers in
+/// `fpzip.rs`.
+pub struct Fpzip {
+    /// Compress as f32 rather than f64.
+    pub single: bool,
+    /// Bits of precision kept, 0 for all of them.
+    pub precision: u32,
+}
+
+impl FloatCodec for Fpzip {
+    fn name(&self) -> String {
+        "fpzip".to_string()
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let float_type = if self.single { "f32" } else { "f64" };
+        vec![
+            ("type", float_type.to_string()),
+            ("precision", self.precision.to_string()),
+        ]
+    }
+
+    // f32 rounds f64 input whatever the precision, and f64 is lossless at 0
+    // (all bits) as well as at 64
+    fn lossy(&self) -> bool {
+        self.single || (self.precision != 0 && self.precision < 64)
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        if self.single {
+            Ok(Stage::Singles(src.iter().map(|&v| v as f32).collect()))
+        } else {
+            Ok(Stage::Floats(Cow::Borrowed(src)))
+        }
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        if self.single {
+            fpzip_compress(prepared.singles()?, self.precision)
+        } else {
+            fpzip_compress(prepared.floats()?, self.precision)
+        }
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        if self.single {
+            Ok(Stage::Singles(fpzip_decompress(bytes)?))
+        } else {
+            Ok(Stage::Floats(Cow::Owned(fpzip_decompress(bytes)?)))
+        }
+    }
+}
+
+/// The little endian bytes as they are, as a baseline and as the end of
+/// pipelines that only transform.
+pub struct Raw;
+
+impl FloatCodec for Raw {
+    fn name(&self) -> String {
+        "none".to_string()
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        split_bytes_stage(src, false)
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        Ok(prepared.bytes()?.to_vec())
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        if !bytes.len().is_multiple_of(8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} bytes are not a whole number of floats", bytes.len()),
+            ));
+        }
+        Ok(Stage::Bytes(bytes.to_vec()))
+    }
+}
+
+/// Transforms followed by a lossless codec, see `pipeline.rs`.
+pub struct Pipeline {
+    pub transforms: Vec<Transform>,
+    pub codec: Box<dyn FloatCodec>,
+}
+
+impl Pipeline {
+    // the values after every transform, as the codec gets them
+    fn forward(&self, src: &[f64]) -> Vec<f64> {
+        let mut words: Vec<u64> = src.iter().map(|v| v.to_bits()).collect();
+        for transform in &self.transforms {
+            transform.forward(&mut words);
+        }
+        words.into_iter().map(f64::from_bits).collect()
+    }
+}
+
+impl FloatCodec for Pipeline {
+    fn name(&self) -> String {
+        let mut names: Vec<String> = self.transforms.iter().map(Transform::to_string).collect();
+        names.push(self.codec.name());
+        names.join("-")
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let transforms: Vec<&str> = self.transforms.iter().map(Transform::name).collect();
+        let mut params = vec![("transforms", transforms.join("|"))];
+        params.extend(self.codec.params());
+        params
+    }
+
+    fn lossy(&self) -> bool {
+        self.codec.lossy()
+    }
+
+    fn prepare<'a>(&self, src: &'a [f64]) -> io::Result<Stage<'a>> {
+        Ok(self.codec.prepare(&self.forward(src))?.into_owned())
+    }
+
+    // the timestamps pass the transforms untouched, for gorilla_ts and tsz
+    fn prepare_timestamped<'a>(&self, times: &[u64], src: &'a [f64]) -> io::Result<Stage<'a>> {
+        Ok(self
+            .codec
+            .prepare_timestamped(times, &self.forward(src))?
+            .into_owned())
+    }
+
+    fn encode_prepared(&self, prepared: &Stage) -> io::Result<Vec<u8>> {
+        self.codec.encode_prepared(prepared)
+    }
+
+    fn decode_raw(&self, bytes: &[u8]) -> io::Result<Stage<'static>> {
+        self.codec.decode_raw(bytes)
+    }
+
+    fn finalize(&self, decoded: Stage) -> io::Result<Vec<f64>> {
+        let mut words: Vec<u64> = self
+            .codec
+            .finalize(decoded)?
+            .into_iter()
+            .map(f64::to_bits)
+            .collect();
+        for transform in self.transforms.iter().rev() {
+            transform.inverse(&mut words)?;
+        }
+        Ok(words.into_iter().map(f64::from_bits).collect())
+    }
+}
@@ -0,0 +1,219 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// Marks the start of a file written by `compress`.
+const MAGIC: &[u8; 4] = b"FCDC";
+/// Marks the start of a file that also stores the files its spec names.
+const MAGIC_WITH_FILES: &[u8; 4] = b"FCD2";
+
+/// Header of a compressed file: the spec of the codec that produced the pages,
+/// e.g. `zstd:level=3`, and the contents of the files the spec names, such as
+/// the dictionary of `zstd_dict`, keyed by the path in the spec.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub spec: String,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+/// One compressed page together with the number of values it holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    pub num_values: u32,
+    pub bytes: Vec<u8>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// the length of a field, refusing what does not fit its integer type rather
+// than writing a header that reads back wrong
+pub(crate) fn field_len<T: TryFrom<usize>>(len: usize, field: &str) -> io::Result<T> {
+    T::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} of {} is too long for the file format", field, len),
+        )
+    })
+}
+
+// reads `len` bytes without allocating them up front, so a corrupt length
+// cannot allocate more than the rest of the input
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "expected {} bytes, the file ends after {}",
+                len,
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(bytes)
+}
+
+// layout:
+//   magic (4 bytes), FCDC or, with files, FCD2
+//   codec spec length (u16) + codec spec (utf8)
+//   only after FCD2: number of files (u16), and for each file its path length
+//     (u16) + path (utf8), number of bytes (u32) + bytes
+//   number of pages (u32)
+//   for each page: number of values (u32), number of bytes (u32), bytes
+// all integers are little endian
+pub fn write<W: Write>(w: &mut W, header: &Header, pages: &[Page]) -> io::Result<()> {
+    // files without any stay readable by older versions
+    w.write_all(if header.files.is_empty() {
+        MAGIC
+    } else {
+        MAGIC_WITH_FILES
+    })?;
+    w.write_u16::<LittleEndian>(field_len(header.spec.len(), "codec spec")?)?;
+    w.write_all(header.spec.as_bytes())?;
+    if !header.files.is_empty() {
+        w.write_u16::<LittleEndian>(field_len(header.files.len(), "file count")?)?;
+        for (path, contents) in &header.files {
+            w.write_u16::<LittleEndian>(field_len(path.len(), "file path")?)?;
+            w.write_all(path.as_bytes())?;
+            w.write_u32::<LittleEndian>(field_len(contents.len(), "file")?)?;
+            w.write_all(contents)?;
+        }
+    }
+    w.write_u32::<LittleEndian>(field_len(pages.len(), "page count")?)?;
+    for page in pages {
+        w.write_u32::<LittleEndian>(page.num_values)?;
+        w.write_u32::<LittleEndian>(field_len(page.bytes.len(), "page")?)?;
+        w.write_all(&page.bytes)?;
+    }
+    Ok(())
+}
+
+pub fn read<R: Read>(r: &mut R) -> io::Result<(Header, Vec<Page>)> {
+    let mut magic = [0_u8; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC && &magic != MAGIC_WITH_FILES {
+        return Err(invalid_data("not a compressed float file"));
+    }
+
+    let spec_len = r.read_u16::<LittleEndian>()? as usize;
+    let spec = read_bytes(r, spec_len)?;
+    let spec = String::from_utf8(spec).map_err(|_| invalid_data("codec spec is not utf8"))?;
+    let mut files = Vec::new();
+    if &magic == MAGIC_WITH_FILES {
+        let num_files = r.read_u16::<LittleEndian>()?;
+        for _ in 0..num_files {
+            let path_len = r.read_u16::<LittleEndian>()? as usize;
+            let path = String::from_utf8(read_bytes(r, path_len)?)
+                .map_err(|_| invalid_data("file path is not utf8"))?;
+            let num_bytes = r.read_u32::<LittleEndian>()? as usize;
+            files.push((path, read_bytes(r, num_bytes)?));
+        }
+    }
+    let header = Header { spec, files };
+
+    let num_pages = r.read_u32::<LittleEndian>()?;
+    let mut pages = Vec::new();
+    for _ in 0..num_pages {
+        let num_values = r.read_u32::<LittleEndian>()?;
+        let num_bytes = r.read_u32::<LittleEndian>()? as usize;
+        let bytes = read_bytes(r, num_bytes)?;
+        pages.push(Page { num_values, bytes });
+    }
+    Ok((header, pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(files: Vec<(String, Vec<u8>)>) -> Header {
+        Header {
+            spec: "zstd:level=3,split=false".to_string(),
+            files,
+        }
+    }
+
+    fn pages() -> Vec<Page> {
+        vec![
+            Page {
+                num_values: 3,
+                bytes: vec![1, 2, 3, 4],
+            },
+            Page {
+                num_values: 0,
+                bytes: Vec::new(),
+            },
+        ]
+    }
+
+    fn written(header: &Header, pages: &[Page]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, header, pages).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_without_files() {
+        let (header, pages) = (header(Vec::new()), pages());
+        let bytes = written(&header, &pages);
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(read(&mut &bytes[..]).unwrap(), (header, pages));
+    }
+
+    #[test]
+    fn round_trips_with_files() {
+        let header = header(vec![
+            ("results/open/open.dict".to_string(), vec![0x37, 0xa4, 0x30]),
+            ("empty".to_string(), Vec::new()),
+        ]);
+        let bytes = written(&header, &pages());
+        assert_eq!(&bytes[..4], MAGIC_WITH_FILES);
+        assert_eq!(read(&mut &bytes[..]).unwrap(), (header, pages()));
+    }
+
+    #[test]
+    fn round_trips_no_pages() {
+        let header = header(Vec::new());
+        let bytes = written(&header, &[]);
+        assert_eq!(read(&mut &bytes[..]).unwrap(), (header, Vec::new()));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let err = read(&mut &b"PK\x03\x04 and more"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_specs_that_are_not_utf8() {
+        let mut bytes = written(&header(Vec::new()), &[]);
+        bytes[6] = 0xff;
+        let err = read(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let header = header(vec![("open.dict".to_string(), vec![1, 2, 3])]);
+        let bytes = written(&header, &pages());
+        for len in 0..bytes.len() {
+            let err = read(&mut &bytes[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn refuses_fields_too_long_for_the_format() {
+        let header = Header {
+            spec: "x".repeat(1 << 16),
+            files: Vec::new(),
+        };
+        let err = write(&mut Vec::new(), &header, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(field_len::<u32>(u32::MAX as usize, "page").is_ok());
+        assert!(field_len::<u32>(u32::MAX as usize + 1, "page").is_err());
+    }
+}
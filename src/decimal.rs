@@ -0,0 +1,329 @@
+//! Decimal values stored as scaled integers, using `rust_decimal` for the
+//! conversions.
+//!
+//! Every value is turned into the shortest decimal that parses back to it, so
+//! `149.8697` becomes the mantissa 1498697 with scale 4. All values of a page
+//! are brought to the page's largest scale, which turns them into integers of
+//! the same unit. An integer is only kept if converting it back with
+//! `rust_decimal` gives exactly the original bits. Values for which that fails,
+//! like NaN, infinities, -0.0 or values with more digits than fit in an `i64`,
+//! are stored as raw bits instead and the integer sequence repeats the previous
+//! integer in their place. The integers are then delta encoded, zig-zag encoded
+//! and bit-packed.
+//!
+//! A page holds, all little endian:
+//!
+//! * the value count (u32), the scale (u8) and the bit width (u8)
+//! * the first integer (i64)
+//! * the raw value count (u32), then each raw value as its position (u32) and
+//!   its bits (u64)
+//! * the packed deltas of all values after the first, most significant bit
+//!   first
+
+use bit_streamer::{Reader, Writer};
+use byteorder::{ByteOrder, LittleEndian};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+use std::io;
+use std::str::FromStr;
+
+// 10^18 still fits in an i64
+const MAX_SCALE: u32 = 18;
+const HEADER_BYTES: usize = 4 + 2 + 8 + 4;
+const RAW_BYTES: usize = 4 + 8;
+
+// the shortest decimal for `value`, if it has one that rust_decimal can hold
+fn to_decimal(value: f64) -> Option<Decimal> {
+    if !value.is_finite() {
+        return None;
+    }
+    // Display prints the shortest digits that parse back to the same value
+    Decimal::from_str(&value.to_string())
+        .ok()
+        .filter(|d| d.scale() <= MAX_SCALE)
+}
+
+fn to_float(n: i64, scale: u32) -> Option<f64> {
+    Decimal::from_i128_with_scale(n as i128, scale).to_f64()
+}
+
+// the integer for `decimal` at `scale`, if converting it back restores `value`
+fn scaled(value: f64, decimal: Decimal, scale: u32) -> Option<i64> {
+    let n = 10_i128
+        .checked_pow(scale - decimal.scale())
+        .and_then(|power| decimal.mantissa().checked_mul(power))
+        .and_then(|n| i64::try_from(n).ok())?;
+    match to_float(n, scale) {
+        Some(restored) if restored.to_bits() == value.to_bits() => Some(n),
+        _ => None,
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+pub fn decimal_encode(src: &[f64], dst: &mut Vec<u8>) -> io::Result<()> {
+    let count = u32::try_from(src.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "decimal pages hold at most u32::MAX values",
+        )
+    })?;
+
+    let decimals: Vec<Option<Decimal>> = src.iter().map(|&value| to_decimal(value)).collect();
+    let scale = decimals
+        .iter()
+        .flatten()
+        .map(Decimal::scale)
+        .max()
+        .unwrap_or(0);
+
+    let exact: Vec<Option<i64>> = src
+        .iter()
+        .zip(decimals)
+        .map(|(&value, decimal)| decimal.and_then(|decimal| scaled(value, decimal, scale)))
+        .collect();
+    // raw values repeat the previous integer, leading ones the first integer, so
+    // that they add no deltas
+    let mut last = exact.iter().flatten().next().copied().unwrap_or(0);
+    let mut ints = Vec::with_capacity(src.len());
+    let mut raw = Vec::new();
+    for (i, (&value, n)) in src.iter().zip(exact).enumerate() {
+        match n {
+            Some(n) => last = n,
+            None => raw.push((i as u32, value.to_bits())),
+        }
+        ints.push(last);
+    }
+
+    let deltas: Vec<u64> = ints
+        .windows(2)
+        .map(|pair| zigzag(pair[1].wrapping_sub(pair[0])))
+        .collect();
+    let width = deltas
+        .iter()
+        .map(|delta| 64 - delta.leading_zeros())
+        .max()
+        .unwrap_or(0);
+
+    dst.extend_from_slice(&count.to_le_bytes());
+    dst.extend_from_slice(&[scale as u8, width as u8]);
+    dst.extend_from_slice(&ints.first().copied().unwrap_or(0).to_le_bytes());
+    dst.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+    for (position, bits) in raw {
+        dst.extend_from_slice(&position.to_le_bytes());
+        dst.extend_from_slice(&bits.to_le_bytes());
+    }
+
+    if width > 0 {
+        let mut writer = Writer::new(dst);
+        for delta in deltas {
+            writer.write_bits(delta as u128, width as usize)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+pub fn decimal_decode(bytes: &[u8]) -> io::Result<Vec<f64>> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "decimal page is truncated");
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let header = bytes.get(..HEADER_BYTES).ok_or_else(truncated)?;
+    let count = LittleEndian::read_u32(header) as usize;
+    let (scale, width) = (header[4] as u32, header[5] as usize);
+    let first = LittleEndian::read_i64(&header[6..]);
+    let raw_count = LittleEndian::read_u32(&header[14..]) as usize;
+    if scale > MAX_SCALE || width > 64 {
+        return Err(invalid(format!(
+            "decimal page has scale {} and bit width {}",
+            scale, width
+        )));
+    }
+
+    let raw = raw_count
+        .checked_mul(RAW_BYTES)
+        .and_then(|len| bytes.get(HEADER_BYTES..HEADER_BYTES + len))
+        .ok_or_else(truncated)?;
+    let packed = &bytes[HEADER_BYTES + raw.len()..];
+    // every value after the first is a delta of `width` bits, so a count the
+    // rest of the page cannot hold is rejected before allocating for it
+    let packed_bits = count
+        .saturating_sub(1)
+        .checked_mul(width)
+        .ok_or_else(truncated)?;
+    if packed.len() < packed_bits.div_ceil(8) {
+        return Err(truncated());
+    }
+    let mut reader = Reader::new(packed);
+
+    let mut values = Vec::with_capacity(count);
+    let mut n = first;
+    for i in 0..count {
+        if i > 0 && width > 0 {
+            n = n.wrapping_add(unzigzag(reader.read_bits(width)? as u64));
+        }
+        let value =
+            to_float(n, scale).ok_or_else(|| invalid(format!("decimal value {} has no f64", i)))?;
+        values.push(value);
+    }
+
+    for entry in raw.chunks_exact(RAW_BYTES) {
+        let position = LittleEndian::read_u32(entry) as usize;
+        let value = values.get_mut(position).ok_or_else(|| {
+            invalid(format!(
+                "decimal raw value at {} is outside the page of {} values",
+                position, count
+            ))
+        })?;
+        *value = f64::from_bits(LittleEndian::read_u64(&entry[4..]));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[f64]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        decimal_encode(values, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        decimal_decode(&encode(values)).unwrap()
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    fn raw_count(bytes: &[u8]) -> u32 {
+        LittleEndian::read_u32(&bytes[14..])
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    // prices with up to four decimals
+    fn decimals(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|i| (1_000_000 + i * 37 % 9973) as f64 / 10000.0)
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_bits_eq(&[-1.25], &round_trip(&[-1.25]));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn encodes_decimals_without_raw_values() {
+        let values = decimals(1000);
+        let bytes = encode(&values);
+        assert_eq!(raw_count(&bytes), 0);
+        assert_eq!(bytes[4], 4);
+        assert_bits_eq(&values, &decimal_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn stores_what_does_not_come_back_as_raw_values() {
+        let mut values = decimals(1000);
+        values[0] = f64::NAN;
+        values[500] = -0.0;
+        values[999] = f64::INFINITY;
+        let bytes = encode(&values);
+        assert_eq!(raw_count(&bytes), 3);
+        assert_bits_eq(&values, &decimal_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn zigzag_round_trips_the_extremes() {
+        for n in [0, 1, -1, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(n)), n);
+        }
+    }
+
+    #[test]
+    fn rejects_counts_the_packed_deltas_cannot_hold() {
+        let mut bytes = encode(&decimals(100));
+        bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = decimal_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_raw_values_outside_the_page() {
+        let mut values = decimals(10);
+        values[9] = f64::NAN;
+        let mut bytes = encode(&values);
+        bytes[HEADER_BYTES..HEADER_BYTES + 4].copy_from_slice(&10_u32.to_le_bytes());
+        let err = decimal_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let mut values = decimals(20);
+        values[7] = f64::NAN;
+        let bytes = encode(&values);
+        for len in 0..bytes.len() {
+            let err = decimal_decode(&bytes[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Trained zstd dictionaries.
+//!
+//! Every page is compressed on its own, so zstd starts each one without any
+//! knowledge of the data and small pages spend much of their size on literals
+//! and tables that the previous page already had. A dictionary trained on
+//! sample pages of a dataset gives zstd that knowledge up front; it has to be
+//! the same for compressing and decompressing.
+//!
+//! A dictionary file holds the bytes `zstd::dict::from_samples` returns, which
+//! carry zstd's own magic number and dictionary id. Frames record the id, so a
+//! page decompressed with another dictionary fails instead of decoding to
+//! garbage. `compress` stores the dictionary in its output, so decompressing
+//! does not need the file.
+
+use std::io;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::byte_stream_split::split_streams;
+use crate::codec::f64s_to_bytes;
+
+/// Trains a dictionary of at most `max_size` bytes on `values` cut into
+/// samples of `sample_values` values, as the pages it is meant for would be.
+/// With `split` the samples are byte stream split first, like the pages of
+/// `zstd:split=true`.
+pub fn train(
+    values: &[f64],
+    sample_values: usize,
+    split: bool,
+    max_size: usize,
+) -> io::Result<Vec<u8>> {
+    if sample_values == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "samples need at least one value",
+        ));
+    }
+    let samples: Vec<Vec<u8>> = values
+        .chunks(sample_values)
+        .map(|sample| {
+            let bytes = f64s_to_bytes(sample);
+            if split {
+                split_streams(&bytes, 8)
+            } else {
+                bytes
+            }
+        })
+        .collect();
+    // zstd reports too few or too small samples only by its error code
+    zstd::dict::from_samples(&samples, max_size).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "training a dictionary on {} samples of {} values failed: {} \
+                (try smaller samples or more input)",
+                samples.len(),
+                sample_values,
+                e
+            ),
+        )
+    })
+}
+
+// what trained dictionaries start with, as opposed to raw content
+const DICTIONARY_MAGIC: [u8; 4] = 0xEC30A437_u32.to_le_bytes();
+
+/// A dictionary digested once for a compression level.
+pub struct Dictionary {
+    /// The file it was read from.
+    pub path: String,
+    pub encoder: EncoderDictionary<'static>,
+    pub decoder: DecoderDictionary<'static>,
+}
+
+impl Dictionary {
+    pub fn new(path: &str, bytes: &[u8], level: i32) -> io::Result<Dictionary> {
+        if !bytes.starts_with(&DICTIONARY_MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a zstd dictionary", path),
+            ));
+        }
+        Ok(Dictionary {
+            path: path.to_string(),
+            encoder: EncoderDictionary::copy(bytes, level),
+            decoder: DecoderDictionary::copy(bytes),
+        })
+    }
+}
@@ -0,0 +1,215 @@
+//! Elf, from "Elf: Erasing-based Lossless Floating-Point Compression"
+//! (Li et al., 2023).
+//!
+//! Values that come from decimals, like prices with two decimal places, carry
+//! mantissa bits that only exist because the decimal has no exact binary
+//! representation. Elf erases those bits before XOR compression so the XORs
+//! get more trailing zeros, and restores the value on decoding by rounding up
+//! at the value's number of decimal places `alpha`.
+//!
+//! A page holds the length of the flag section (u32, little endian), the flag
+//! section, then a Chimp stream of the erased values. The flag section has a 0
+//! bit for every value stored as is, or a 1 bit and `alpha` in 4 bits for every
+//! erased value. Unlike the paper, which stores `alpha` only when it changes,
+//! it is written for every erased value. A value is only erased if restoring it
+//! gives back exactly the original bits, so Elf stays lossless.
+
+use bit_streamer::{Reader, Writer};
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io;
+
+use crate::chimp::{chimp_decode, ChimpEncoder};
+
+const MAX_ALPHA: i32 = 15;
+const MANTISSA_BITS: i32 = 52;
+
+// exact in f64, unlike powi, which may round differently between platforms
+const POWERS_OF_TEN: [f64; MAX_ALPHA as usize + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15,
+];
+
+// smallest number of decimal places that still prints as exactly `value`
+fn decimal_places(value: f64) -> Option<i32> {
+    (0..=MAX_ALPHA).find(|&alpha| {
+        format!("{:.*}", alpha as usize, value)
+            .parse::<f64>()
+            .is_ok_and(|parsed| parsed.to_bits() == value.to_bits())
+    })
+}
+
+// rounds an erased value away from zero at `alpha` decimal places
+fn restore(erased: f64, alpha: i32) -> f64 {
+    let scale = POWERS_OF_TEN[alpha as usize];
+    (erased.abs() * scale).ceil().copysign(erased) / scale
+}
+
+// the value with as many low mantissa bits cleared as can be restored exactly,
+// or None if nothing can be erased
+fn erase(value: f64) -> Option<(f64, i32)> {
+    if !value.is_normal() {
+        return None;
+    }
+    let alpha = decimal_places(value)?;
+    let bits = value.to_bits();
+    let exponent = ((bits >> MANTISSA_BITS) & 0x7ff) as i32 - 1023;
+    // erasing g bits changes the value by less than 2^(exponent - 52 + g), which
+    // must stay below 10^-alpha for the rounding to restore it
+    let max_erased = (MANTISSA_BITS as f64 - exponent as f64 - alpha as f64 * 10_f64.log2())
+        .floor()
+        .min(MANTISSA_BITS as f64);
+    for erased_bits in (1..=max_erased as i32).rev() {
+        let erased = bits & !((1_u64 << erased_bits) - 1);
+        if erased == bits {
+            return None;
+        }
+        if restore(f64::from_bits(erased), alpha).to_bits() == bits {
+            return Some((f64::from_bits(erased), alpha));
+        }
+    }
+    None
+}
+
+pub fn elf_encode(src: &[f64], dst: &mut Vec<u8>) -> io::Result<()> {
+    let mut flags = Vec::new();
+    let mut erased_values = Vec::with_capacity(src.len());
+    {
+        let mut writer = Writer::new(&mut flags);
+        for &value in src {
+            match erase(value) {
+                Some((erased, alpha)) => {
+                    writer.write_bit(true)?;
+                    writer.write_bits(alpha as u128, 4)?;
+                    erased_values.push(erased);
+                }
+                None => {
+                    writer.write_bit(false)?;
+                    erased_values.push(value);
+                }
+            }
+        }
+        writer.flush()?;
+    }
+
+    let flags_len = u32::try_from(flags.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "elf page is too large"))?;
+    dst.extend_from_slice(&flags_len.to_le_bytes());
+    dst.extend_from_slice(&flags);
+    ChimpEncoder::new(dst, 0).encode(&erased_values)
+}
+
+pub fn elf_decode(bytes: &[u8]) -> io::Result<Vec<f64>> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "elf page is truncated");
+    if bytes.len() < 4 {
+        return Err(truncated());
+    }
+    let flags_len = LittleEndian::read_u32(bytes) as usize;
+    let flags = bytes.get(4..4 + flags_len).ok_or_else(truncated)?;
+    let mut values = chimp_decode(&bytes[4 + flags_len..], 0)?;
+
+    let mut reader = Reader::new(flags);
+    for value in values.iter_mut() {
+        if reader.read_bit()? {
+            let alpha = reader.read_bits(4)? as i32;
+            *value = restore(*value, alpha);
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[f64]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        elf_encode(values, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        elf_decode(&encode(values)).unwrap()
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_bits_eq(&[-1.25], &round_trip(&[-1.25]));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_decimals() {
+        let values: Vec<f64> = (0..1000).map(|i| (i * 37 % 10007) as f64 / 100.0).collect();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn erasing_shrinks_decimals_below_chimp() {
+        let values: Vec<f64> = (0..1000).map(|i| 100.0 + (i % 97) as f64 * 0.01).collect();
+        let mut chimp = Vec::new();
+        ChimpEncoder::new(&mut chimp, 0).encode(&values).unwrap();
+        assert!(encode(&values).len() < chimp.len());
+    }
+
+    #[test]
+    fn rejects_flags_longer_than_the_page() {
+        let mut bytes = encode(&[1.5, 2.25]);
+        bytes[..4].copy_from_slice(&1000_u32.to_le_bytes());
+        let err = elf_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,253 @@
+//! FPC, from "FPC: A High-Speed Compressor for Double-Precision Floating-Point
+//! Data" (Burtscher and Ratanaworabhan, 2009).
+//!
+//! Two hash tables predict every value from the values before it: the finite
+//! context method (FCM) table holds the value that followed the last time the
+//! same recent history was seen, the differential FCM (DFCM) table holds the
+//! difference to the previous value that followed. The prediction that shares
+//! more leading zero bytes with the value is XORed with it and only the
+//! remaining bytes are stored.
+//!
+//! A page holds the value count (u32, little endian) and the log2 of the table
+//! size (u8), then one 4 bit header per value, two to a byte with the first
+//! value in the high bits, then the residual bytes of every value. A header is
+//! the predictor bit (1 for DFCM) followed by 3 bits of leading zero bytes.
+//! Since 3 bits cannot hold 0 to 8, 4 leading zero bytes are stored as 3, which
+//! costs one zero byte for the rare values with exactly 4. The residuals are the
+//! low bytes of the XOR, little endian.
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io;
+
+pub const MAX_LEVEL: u32 = 24;
+
+struct Predictor {
+    fcm: Vec<u64>,
+    dfcm: Vec<u64>,
+    fcm_hash: usize,
+    dfcm_hash: usize,
+    last: u64,
+}
+
+impl Predictor {
+    fn new(level: u32) -> Self {
+        Predictor {
+            fcm: vec![0; 1 << level],
+            dfcm: vec![0; 1 << level],
+            fcm_hash: 0,
+            dfcm_hash: 0,
+            last: 0,
+        }
+    }
+
+    // the FCM and DFCM predictions for the next value
+    fn predict(&self) -> (u64, u64) {
+        (
+            self.fcm[self.fcm_hash],
+            self.dfcm[self.dfcm_hash].wrapping_add(self.last),
+        )
+    }
+
+    fn update(&mut self, value: u64) {
+        let mask = self.fcm.len() - 1;
+        let delta = value.wrapping_sub(self.last);
+        self.fcm[self.fcm_hash] = value;
+        self.fcm_hash = ((self.fcm_hash << 6) ^ (value >> 48) as usize) & mask;
+        self.dfcm[self.dfcm_hash] = delta;
+        self.dfcm_hash = ((self.dfcm_hash << 2) ^ (delta >> 40) as usize) & mask;
+        self.last = value;
+    }
+}
+
+// 3 bit code for a count of leading zero bytes, and the count it stands for
+fn leading_code(leading_bytes: u32) -> (u8, u32) {
+    match leading_bytes {
+        0..=3 => (leading_bytes as u8, leading_bytes),
+        4 => (3, 3),
+        _ => (leading_bytes as u8 - 1, leading_bytes),
+    }
+}
+
+fn leading_bytes(code: u8) -> u32 {
+    if code < 4 {
+        code as u32
+    } else {
+        code as u32 + 1
+    }
+}
+
+/// Encodes `src` with tables of `1 << level` entries each.
+pub fn fpc_encode(src: &[f64], level: u32, dst: &mut Vec<u8>) -> io::Result<()> {
+    let count = u32::try_from(src.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fpc pages hold at most u32::MAX values",
+        )
+    })?;
+    dst.extend_from_slice(&count.to_le_bytes());
+    dst.push(level as u8);
+
+    let mut predictor = Predictor::new(level);
+    let mut headers = vec![0_u8; src.len().div_ceil(2)];
+    let mut residuals = Vec::with_capacity(src.len() * 8);
+    for (i, value) in src.iter().enumerate() {
+        let value = value.to_bits();
+        let (fcm, dfcm) = predictor.predict();
+        let (fcm_xor, dfcm_xor) = (value ^ fcm, value ^ dfcm);
+        let (use_dfcm, xor) = if dfcm_xor.leading_zeros() > fcm_xor.leading_zeros() {
+            (1, dfcm_xor)
+        } else {
+            (0, fcm_xor)
+        };
+        let (code, leading) = leading_code(xor.leading_zeros() / 8);
+
+        headers[i / 2] |= (use_dfcm << 3 | code) << if i % 2 == 0 { 4 } else { 0 };
+        residuals.extend_from_slice(&xor.to_le_bytes()[..(8 - leading) as usize]);
+        predictor.update(value);
+    }
+    dst.extend_from_slice(&headers);
+    dst.extend_from_slice(&residuals);
+    Ok(())
+}
+
+pub fn fpc_decode(bytes: &[u8]) -> io::Result<Vec<f64>> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "fpc page is truncated");
+    if bytes.len() < 5 {
+        return Err(truncated());
+    }
+    let count = LittleEndian::read_u32(bytes) as usize;
+    let level = bytes[4] as u32;
+    if level > MAX_LEVEL {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("fpc page has level {}, at most {}", level, MAX_LEVEL),
+        ));
+    }
+    let headers = bytes.get(5..5 + count.div_ceil(2)).ok_or_else(truncated)?;
+    let mut pos = 5 + headers.len();
+
+    let mut predictor = Predictor::new(level);
+    let mut values = Vec::new();
+    for i in 0..count {
+        let header = headers[i / 2] >> if i % 2 == 0 { 4 } else { 0 };
+        let residual_bytes = (8 - leading_bytes(header & 0x7)) as usize;
+        let mut xor = [0_u8; 8];
+        xor[..residual_bytes]
+            .copy_from_slice(bytes.get(pos..pos + residual_bytes).ok_or_else(truncated)?);
+        pos += residual_bytes;
+
+        let (fcm, dfcm) = predictor.predict();
+        let prediction = if header & 0x8 != 0 { dfcm } else { fcm };
+        let value = prediction ^ u64::from_le_bytes(xor);
+        predictor.update(value);
+        values.push(f64::from_bits(value));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[f64], level: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        fpc_encode(values, level, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_round_trips(values: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        for level in [0, 1, 10, 16] {
+            let decoded = fpc_decode(&encode(values, level)).unwrap();
+            assert_eq!(bits(values), bits(&decoded), "level {}", level);
+        }
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert_round_trips(&[]);
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_round_trips(&[-1.25]);
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        assert_round_trips(&special_values());
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        assert_round_trips(&random_values(1001));
+    }
+
+    #[test]
+    fn leading_codes_never_claim_more_zero_bytes_than_there_are() {
+        for leading in 0..=8 {
+            let (code, stored) = leading_code(leading);
+            assert!(code < 8);
+            assert!(stored <= leading);
+            assert_eq!(leading_bytes(code), stored);
+        }
+    }
+
+    #[test]
+    fn predicts_repeating_sequences() {
+        let values: Vec<f64> = (0..1000).map(|i| (i % 10) as f64 * 0.1).collect();
+        // mostly a header of half a byte per value
+        assert!(encode(&values, 16).len() < values.len());
+    }
+
+    #[test]
+    fn rejects_levels_above_the_maximum() {
+        let mut bytes = encode(&[1.5], 10);
+        bytes[4] = MAX_LEVEL as u8 + 1;
+        let err = fpc_decode(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let bytes = encode(&random_values(20), 10);
+        for len in 0..bytes.len() {
+            let err = fpc_decode(&bytes[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Safe wrappers around `fpzip_sys`.
+//!
+//! fpzip predicts every value from its neighbours and entropy codes the
+//! difference. At full precision it is lossless, with fewer bits of precision
+//! it drops low mantissa bits before predicting. It compresses either f32 or
+//! f64 arrays, so the f32 mode is lossy for f64 input whatever the precision.
+//!
+//! A page holds the number of values (u32, little endian) followed by fpzip's
+//! own stream, which starts with a header giving the type, precision and
+//! dimensions. fpzip reads from a buffer without knowing its length, so
+//! decoding copies the page into a buffer padded with zeros, sized for the
+//! largest stream that number of values can take. The header is checked
+//! against the count before anything is allocated for the values.
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::io;
+use std::os::raw::c_int;
+
+/// A type fpzip compresses.
+pub trait Element: Copy + Default {
+    const TYPE: c_int;
+    const BITS: u32;
+}
+
+impl Element for f32 {
+    const TYPE: c_int = fpzip_sys::FPZIP_TYPE_FLOAT as c_int;
+    const BITS: u32 = 32;
+}
+
+impl Element for f64 {
+    const TYPE: c_int = fpzip_sys::FPZIP_TYPE_DOUBLE as c_int;
+    const BITS: u32 = 64;
+}
+
+// room for fpzip's header and for streams longer than the input
+const SLACK_BYTES: usize = 1024;
+
+fn fpzip_error(msg: &str) -> io::Error {
+    io::Error::other(msg)
+}
+
+fn max_stream_bytes<T: Element>(count: usize) -> usize {
+    count * std::mem::size_of::<T>() + SLACK_BYTES
+}
+
+/// An open fpzip stream, closed on drop.
+struct Fpz {
+    raw: *mut fpzip_sys::FPZ,
+    reading: bool,
+}
+
+impl Drop for Fpz {
+    fn drop(&mut self) {
+        unsafe {
+            if self.reading {
+                fpzip_sys::fpzip_read_close(self.raw);
+            } else {
+                fpzip_sys::fpzip_write_close(self.raw);
+            }
+        }
+    }
+}
+
+/// Compresses `src` keeping `precision` bits of every value, where 0 keeps
+/// all of them.
+pub fn fpzip_compress<T: Element>(src: &[T], precision: u32) -> io::Result<Vec<u8>> {
+    if precision > T::BITS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "fpzip keeps at most {} bits of precision for this type, not {}",
+                T::BITS,
+                precision
+            ),
+        ));
+    }
+    let count = u32::try_from(src.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fpzip pages hold at most u32::MAX values",
+        )
+    })?;
+    let mut dst = count.to_le_bytes().to_vec();
+    if src.is_empty() {
+        return Ok(dst);
+    }
+
+    let mut buffer = vec![0_u8; max_stream_bytes::<T>(src.len())];
+    let written = {
+        let raw = unsafe {
+            fpzip_sys::fpzip_write_to_buffer(buffer.as_mut_ptr() as *mut c_void, buffer.len() as _)
+        };
+        if raw.is_null() {
+            return Err(fpzip_error("fpzip could not open a stream"));
+        }
+        let fpz = Fpz {
+            raw,
+            reading: false,
+        };
+        unsafe {
+            (*fpz.raw).type_ = T::TYPE;
+            (*fpz.raw).prec = precision as c_int;
+            (*fpz.raw).nx = src.len() as c_int;
+            (*fpz.raw).ny = 1;
+            (*fpz.raw).nz = 1;
+            (*fpz.raw).nf = 1;
+            if fpzip_sys::fpzip_write_header(fpz.raw) == 0 {
+                return Err(fpzip_error("fpzip could not write the header"));
+            }
+            fpzip_sys::fpzip_write(fpz.raw, src.as_ptr() as *const c_void) as usize
+        }
+    };
+    if written == 0 {
+        return Err(fpzip_error("fpzip compression failed"));
+    }
+    dst.extend_from_slice(&buffer[..written]);
+    Ok(dst)
+}
+
+// opens a stream on `buffer`, which has to be padded with zeros past the end
+// of the page, and checks its header against the expected type and count
+fn open_checked<T: Element>(buffer: &[u8], count: usize) -> io::Result<Fpz> {
+    let raw = unsafe { fpzip_sys::fpzip_read_from_buffer(buffer.as_ptr() as *const c_void) };
+    if raw.is_null() {
+        return Err(fpzip_error("fpzip could not open a stream"));
+    }
+    let fpz = Fpz { raw, reading: true };
+    unsafe {
+        if fpzip_sys::fpzip_read_header(fpz.raw) == 0 {
+            return Err(fpzip_error("fpzip could not read the header"));
+        }
+        let header = &*fpz.raw;
+        let dims = [header.nx, header.ny, header.nz, header.nf];
+        if header.type_ != T::TYPE || dims != [count as c_int, 1, 1, 1] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "fpzip header has type {} and dimensions {:?}, expected type {} and {} values",
+                    header.type_,
+                    dims,
+                    T::TYPE,
+                    count
+                ),
+            ));
+        }
+    }
+    Ok(fpz)
+}
+
+pub fn fpzip_decompress<T: Element>(bytes: &[u8]) -> io::Result<Vec<T>> {
+    if bytes.len() < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "fpzip page is truncated",
+        ));
+    }
+    let count = LittleEndian::read_u32(bytes) as usize;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let stream = &bytes[4..];
+    let max_bytes = max_stream_bytes::<T>(count);
+    if stream.len() > max_bytes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "fpzip page of {} values has {} bytes, more than fpzip writes",
+                count,
+                stream.len()
+            ),
+        ));
+    }
+    // the header is checked on a copy of the page's start, before a count it
+    // does not give makes us allocate for the values
+    let mut header = vec![0_u8; SLACK_BYTES];
+    let header_bytes = stream.len().min(SLACK_BYTES);
+    header[..header_bytes].copy_from_slice(&stream[..header_bytes]);
+    open_checked::<T>(&header, count)?;
+
+    let mut values = vec![T::default(); count];
+    let mut buffer = vec![0_u8; max_bytes];
+    buffer[..stream.len()].copy_from_slice(stream);
+    let fpz = open_checked::<T>(&buffer, count)?;
+    if unsafe { fpzip_sys::fpzip_read(fpz.raw, values.as_mut_ptr() as *mut c_void) } == 0 {
+        return Err(fpzip_error("fpzip decompression failed"));
+    }
+    Ok(values)
+}
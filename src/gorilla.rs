@@ -0,0 +1,687 @@
+use bit_streamer::{Reader, Writer};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// Write an end marker that says it's a new value, with more meaningful bits, 0 leading zeros,
+// 64 significant values, then a total value of 0. This cannot happen so it's a safe end marker
+#[allow(clippy::unusual_byte_groupings)]
+const END_MARKER: u128 = 0b11_00000_111111 << (128 - 13);
+
+// Delta-of-delta buckets for timestamps: a delta of deltas of 0 is written as a 0 bit,
+// otherwise n 1 bits and a 0 bit select the nth bucket of this many bits. Four
+// 1 bits select the last bucket of 32 bits. This follows the Gorilla paper except
+// that values are plain two's complement, so the first bucket holds -64..=63
+// rather than -63..=64
+const TIMESTAMP_BUCKET_BITS: [usize; 3] = [7, 9, 12];
+const TIMESTAMP_LAST_BUCKET: u128 = 0b1111;
+
+// A delta of deltas of i32::MIN in the last bucket ends a time series; the encoder
+// never writes it for a timestamp
+const TIMESTAMP_END_MARKER: i32 = i32::MIN;
+
+/// What can go wrong when encoding or decoding a gorilla stream.
+#[derive(Debug)]
+pub enum GorillaError {
+    /// There were no values to encode, or no bytes to decode.
+    EmptyInput,
+    /// The stream ended before its end marker, after `values` values.
+    TruncatedStream {
+        values: usize,
+    },
+    /// The header of value number `value` claims more than 64 bits.
+    InvalidHeader {
+        value: usize,
+        leading_zeros: u32,
+        significant_bits: u32,
+    },
+    /// Timestamp number `value` is before the previous one, or so far from it
+    /// that its delta of deltas does not fit the largest bucket.
+    TimestampOutOfRange {
+        value: usize,
+        timestamp: u64,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for GorillaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GorillaError::EmptyInput => write!(f, "gorilla stream is empty"),
+            GorillaError::TruncatedStream { values } => write!(
+                f,
+                "gorilla stream ends without an end marker after {} values",
+                values
+            ),
+            GorillaError::InvalidHeader {
+                value,
+                leading_zeros,
+                significant_bits,
+            } => write!(
+                f,
+                "gorilla value {} has {} leading zeros and {} significant bits",
+                value, leading_zeros, significant_bits
+            ),
+            GorillaError::TimestampOutOfRange { value, timestamp } => write!(
+                f,
+                "gorilla timestamp {} of value {} is out of range",
+                timestamp, value
+            ),
+            GorillaError::Io(err) => write!(f, "gorilla stream: {}", err),
+        }
+    }
+}
+
+impl Error for GorillaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GorillaError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GorillaError {
+    fn from(err: io::Error) -> Self {
+        GorillaError::Io(err)
+    }
+}
+
+impl From<GorillaError> for io::Error {
+    fn from(err: GorillaError) -> Self {
+        let kind = match &err {
+            GorillaError::Io(err) => return io::Error::new(err.kind(), err.to_string()),
+            GorillaError::EmptyInput => io::ErrorKind::InvalidInput,
+            GorillaError::TruncatedStream { .. } => io::ErrorKind::UnexpectedEof,
+            GorillaError::InvalidHeader { .. } => io::ErrorKind::InvalidData,
+            GorillaError::TimestampOutOfRange { .. } => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+// The XOR half of Gorilla: each value is stored as its XOR with the previous one.
+struct XorEncoder {
+    previous_value: Option<u64>,
+    previous_leading_zeros: u32,
+    previous_trailing_zeros: u32,
+}
+
+impl XorEncoder {
+    fn new() -> Self {
+        XorEncoder {
+            previous_value: None,
+            // Initialize leading and trailing zeros
+            previous_leading_zeros: 64,
+            previous_trailing_zeros: 64,
+        }
+    }
+
+    fn write<W: Write>(&mut self, writer: &mut Writer<W>, next_value: u64) -> io::Result<()> {
+        let previous_value = match self.previous_value.replace(next_value) {
+            Some(previous_value) => previous_value,
+            None => {
+                // First write the first value in full
+                return writer.write_bits(next_value as u128, 64);
+            }
+        };
+        let xor = next_value ^ previous_value;
+
+        if xor == 0 {
+            // If there is no difference from previous value, then we write a 0
+            return writer.write_bit(false);
+        }
+        // Otherwise a 1, followed by more logic to show difference
+        writer.write_bit(true)?;
+
+        // the header has 5 bits for the leading zeros, so more than 31 are
+        // stored as 31 and the extra zeros become significant bits
+        let current_leading_zeros = xor.leading_zeros().min(31);
+        let current_trailing_zeros = xor.trailing_zeros();
+
+        // If block of meaningful bits is within previous meaningful bits
+        if current_leading_zeros >= self.previous_leading_zeros
+            && current_trailing_zeros >= self.previous_trailing_zeros
+        {
+            // Write a zero control bit followed by meaningful bits
+            writer.write_bit(false)?;
+            writer.write_bits(
+                xor.wrapping_shr(self.previous_trailing_zeros) as u128,
+                (64 - self.previous_leading_zeros - self.previous_trailing_zeros) as usize,
+            )
+        } else {
+            // Otherwise, we write a 1 control bit, followed by the 5 bits of the number of
+            // leading zeros, then 6 bits of the number of significant bits
+            // Followed by the significant bits
+            writer.write_bit(true)?;
+            writer.write_bits(current_leading_zeros as u128, 5)?;
+
+            let significant_bits = 64 - current_leading_zeros - current_trailing_zeros;
+            writer.write_bits((significant_bits - 1) as u128, 6)?;
+            writer.write_bits(
+                xor.wrapping_shr(current_trailing_zeros) as u128,
+                significant_bits as usize,
+            )?;
+            self.previous_trailing_zeros = current_trailing_zeros;
+            self.previous_leading_zeros = current_leading_zeros;
+            Ok(())
+        }
+    }
+}
+
+// Reads bits, counting decoded values so that running out of input anywhere
+// before the end marker is reported as a truncated stream.
+struct BitReader<R: Read> {
+    reader: Reader<R>,
+    /// Values decoded so far.
+    values: usize,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(src: R) -> Self {
+        BitReader {
+            reader: Reader::new(src),
+            values: 0,
+        }
+    }
+
+    fn read_bits(&mut self, n: usize) -> Result<u128, GorillaError> {
+        match self.reader.read_bits(n) {
+            Ok(bits) => Ok(bits),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(GorillaError::TruncatedStream {
+                    values: self.values,
+                })
+            }
+            Err(err) => Err(GorillaError::Io(err)),
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<bool, GorillaError> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    // reads the first 64 bits of a stream, telling an empty stream from one cut
+    // off within them
+    fn read_first(&mut self) -> Result<u64, GorillaError> {
+        let sign = match self.reader.read_bit() {
+            Ok(sign) => sign,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(GorillaError::EmptyInput)
+            }
+            Err(err) => return Err(GorillaError::Io(err)),
+        };
+        Ok((sign as u64) << 63 | self.read_bits(63)? as u64)
+    }
+}
+
+struct XorDecoder {
+    previous_value: u64,
+    leading_zeros: u128,
+    trailing_zeros: u128,
+}
+
+impl XorDecoder {
+    fn new(first_value: u64) -> Self {
+        XorDecoder {
+            previous_value: first_value,
+            leading_zeros: 0,
+            trailing_zeros: 0,
+        }
+    }
+
+    // the value after the first, or None at the end marker
+    fn read<R: Read>(&mut self, reader: &mut BitReader<R>) -> Result<Option<u64>, GorillaError> {
+        if !reader.read_bit()? {
+            // If next bit is 0, then it's the same value as previously
+            return Ok(Some(self.previous_value));
+        }
+
+        // next bit was 1 and there's a difference from last bit
+        if reader.read_bit()? {
+            // If control bit is 1, we get number of leading zeros from next 5 bits
+            // then the length of meaningful XORed value in the next 6 bits
+            let leading_zeros = reader.read_bits(5)?;
+            let significant_bits = reader.read_bits(6)? + 1;
+            if leading_zeros + significant_bits > 64 {
+                return Err(GorillaError::InvalidHeader {
+                    value: reader.values,
+                    leading_zeros: leading_zeros as u32,
+                    significant_bits: significant_bits as u32,
+                });
+            }
+            self.leading_zeros = leading_zeros;
+            self.trailing_zeros = 64 - leading_zeros - significant_bits;
+        }
+        let size = 64 - self.leading_zeros - self.trailing_zeros;
+        let next_bits = reader.read_bits(size as usize)? as u64;
+
+        // Check for end marker
+        if self.leading_zeros == 0 && size == 64 && next_bits == 0 {
+            return Ok(None);
+        }
+
+        self.previous_value ^= next_bits << self.trailing_zeros;
+        Ok(Some(self.previous_value))
+    }
+}
+
+/// Gorilla encoder that takes values one at a time and writes the compressed
+/// bits to any `Write`.
+///
+/// The encoder is batch-only: the output is only complete once `finish` has
+/// written the end marker. Until then, bytes reach the `Write` whenever the
+/// bit writer's buffer fills, and there is no flush that would keep the last
+/// partial byte open, as `bit_streamer` can only flush by padding it.
+pub struct GorillaEncoder<W: Write> {
+    writer: Writer<W>,
+    xor: XorEncoder,
+}
+
+impl<W: Write> GorillaEncoder<W> {
+    pub fn new(dst: W) -> Self {
+        GorillaEncoder {
+            writer: Writer::new(dst),
+            xor: XorEncoder::new(),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Result<(), GorillaError> {
+        Ok(self.xor.write(&mut self.writer, value.to_bits())?)
+    }
+
+    pub fn push_all(&mut self, values: &[f64]) -> Result<(), GorillaError> {
+        for &value in values {
+            self.push(value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the end marker and flushes the last partial byte. Fails with
+    /// `EmptyInput` if no value was pushed, as the format needs a first value.
+    pub fn finish(mut self) -> Result<(), GorillaError> {
+        if self.xor.previous_value.is_none() {
+            return Err(GorillaError::EmptyInput);
+        }
+        self.writer.write_bits(END_MARKER, 128)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Gorilla decoder that reads values one at a time from any `Read`, stopping
+/// at the end marker.
+pub struct GorillaDecoder<R: Read> {
+    reader: BitReader<R>,
+    xor: Option<XorDecoder>,
+    done: bool,
+}
+
+impl<R: Read> GorillaDecoder<R> {
+    pub fn new(src: R) -> Self {
+        GorillaDecoder {
+            reader: BitReader::new(src),
+            xor: None,
+            done: false,
+        }
+    }
+
+    fn next_value(&mut self) -> Result<Option<f64>, GorillaError> {
+        let value = match &mut self.xor {
+            Some(xor) => xor.read(&mut self.reader)?,
+            None => {
+                // Read the first value
+                let first = self.reader.read_first()?;
+                self.xor = Some(XorDecoder::new(first));
+                Some(first)
+            }
+        };
+        if value.is_some() {
+            self.reader.values += 1;
+        }
+        Ok(value.map(f64::from_bits))
+    }
+}
+
+impl<R: Read> Iterator for GorillaDecoder<R> {
+    type Item = Result<f64, GorillaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_value().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+pub fn gorilla_encode(src: &[f64], dst: &mut Vec<u8>) -> Result<(), GorillaError> {
+    let mut encoder = GorillaEncoder::new(dst);
+    encoder.push_all(src)?;
+    encoder.finish()
+}
+
+pub fn gorilla_decode(bytes: &[u8], values: &mut Vec<f64>) -> Result<(), GorillaError> {
+    for value in GorillaDecoder::new(bytes) {
+        values.push(value?);
+    }
+    Ok(())
+}
+
+/// Encoder for the full Gorilla time series format, storing each timestamp as
+/// a bucketed delta of deltas in front of its XOR encoded value.
+///
+/// The stream starts with the first timestamp and value in full. Unlike the
+/// paper, which stores the first delta in 14 bits, the first delta is bucketed
+/// like all others as a delta of deltas from 0, so gaps of more than four
+/// hours, such as between daily prices, need no special casing. Timestamps must
+/// not decrease.
+pub struct GorillaTsEncoder<W: Write> {
+    writer: Writer<W>,
+    xor: XorEncoder,
+    previous_timestamp: Option<u64>,
+    previous_delta: i64,
+    values: usize,
+}
+
+impl<W: Write> GorillaTsEncoder<W> {
+    pub fn new(dst: W) -> Self {
+        GorillaTsEncoder {
+            writer: Writer::new(dst),
+            xor: XorEncoder::new(),
+            previous_timestamp: None,
+            previous_delta: 0,
+            values: 0,
+        }
+    }
+
+    pub fn push(&mut self, timestamp: u64, value: f64) -> Result<(), GorillaError> {
+        match self.previous_timestamp {
+            None => self.writer.write_bits(timestamp as u128, 64)?,
+            Some(previous_timestamp) => {
+                let out_of_range = GorillaError::TimestampOutOfRange {
+                    value: self.values,
+                    timestamp,
+                };
+                let delta = match timestamp.checked_sub(previous_timestamp) {
+                    Some(delta) if delta <= i64::MAX as u64 => delta as i64,
+                    _ => return Err(out_of_range),
+                };
+                let delta_of_deltas = delta - self.previous_delta;
+                if delta_of_deltas <= TIMESTAMP_END_MARKER as i64
+                    || delta_of_deltas > i32::MAX as i64
+                {
+                    return Err(out_of_range);
+                }
+                self.write_delta_of_deltas(delta_of_deltas)?;
+                self.previous_delta = delta;
+            }
+        }
+        self.previous_timestamp = Some(timestamp);
+        self.xor.write(&mut self.writer, value.to_bits())?;
+        self.values += 1;
+        Ok(())
+    }
+
+    fn write_delta_of_deltas(&mut self, delta_of_deltas: i64) -> io::Result<()> {
+        if delta_of_deltas == 0 {
+            return self.writer.write_bit(false);
+        }
+        for (i, &bits) in TIMESTAMP_BUCKET_BITS.iter().enumerate() {
+            let limit = 1i64 << (bits - 1);
+            if -limit <= delta_of_deltas && delta_of_deltas < limit {
+                // i + 1 ones and a zero
+                let control = ((1 << (i + 1)) - 1) << 1;
+                self.writer.write_bits(control, i + 2)?;
+                return self
+                    .writer
+                    .write_bits(delta_of_deltas as u128 & ((1 << bits) - 1), bits);
+            }
+        }
+        self.writer.write_bits(TIMESTAMP_LAST_BUCKET, 4)?;
+        self.writer
+            .write_bits(delta_of_deltas as i32 as u32 as u128, 32)
+    }
+
+    /// Writes the end marker and flushes the last partial byte. Fails with
+    /// `EmptyInput` if nothing was pushed.
+    pub fn finish(mut self) -> Result<(), GorillaError> {
+        if self.previous_timestamp.is_none() {
+            return Err(GorillaError::EmptyInput);
+        }
+        self.writer.write_bits(TIMESTAMP_LAST_BUCKET, 4)?;
+        self.writer
+            .write_bits(TIMESTAMP_END_MARKER as u32 as u128, 32)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Decoder for streams written by `GorillaTsEncoder`, yielding
+/// `(timestamp, value)` pairs up to the end marker.
+pub struct GorillaTsDecoder<R: Read> {
+    reader: BitReader<R>,
+    xor: Option<XorDecoder>,
+    previous_timestamp: u64,
+    previous_delta: i64,
+    done: bool,
+}
+
+impl<R: Read> GorillaTsDecoder<R> {
+    pub fn new(src: R) -> Self {
+        GorillaTsDecoder {
+            reader: BitReader::new(src),
+            xor: None,
+            previous_timestamp: 0,
+            previous_delta: 0,
+            done: false,
+        }
+    }
+
+    // sign extends the low `bits` bits
+    fn read_signed(&mut self, bits: usize) -> Result<i64, GorillaError> {
+        let raw = self.reader.read_bits(bits)? as u64;
+        Ok(((raw << (64 - bits)) as i64) >> (64 - bits))
+    }
+
+    // None at the end marker
+    fn read_delta_of_deltas(&mut self) -> Result<Option<i64>, GorillaError> {
+        let mut ones = 0;
+        while ones < TIMESTAMP_BUCKET_BITS.len() + 1 && self.reader.read_bit()? {
+            ones += 1;
+        }
+        if ones == 0 {
+            return Ok(Some(0));
+        }
+        if ones <= TIMESTAMP_BUCKET_BITS.len() {
+            return self.read_signed(TIMESTAMP_BUCKET_BITS[ones - 1]).map(Some);
+        }
+        match self.read_signed(32)? {
+            delta_of_deltas if delta_of_deltas == TIMESTAMP_END_MARKER as i64 => Ok(None),
+            delta_of_deltas => Ok(Some(delta_of_deltas)),
+        }
+    }
+
+    fn next_point(&mut self) -> Result<Option<(u64, f64)>, GorillaError> {
+        let (timestamp, value) = match &self.xor {
+            None => {
+                let timestamp = self.reader.read_first()?;
+                let first = self.reader.read_bits(64)? as u64;
+                self.xor = Some(XorDecoder::new(first));
+                (timestamp, first)
+            }
+            Some(_) => {
+                let delta_of_deltas = match self.read_delta_of_deltas()? {
+                    Some(delta_of_deltas) => delta_of_deltas,
+                    None => return Ok(None),
+                };
+                self.previous_delta += delta_of_deltas;
+                let timestamp = self
+                    .previous_timestamp
+                    .wrapping_add(self.previous_delta as u64);
+                let xor = self.xor.as_mut().unwrap();
+                // the value stream has no end marker of its own here
+                let value = match xor.read(&mut self.reader)? {
+                    Some(value) => value,
+                    None => {
+                        return Err(GorillaError::InvalidHeader {
+                            value: self.reader.values,
+                            leading_zeros: 0,
+                            significant_bits: 64,
+                        })
+                    }
+                };
+                (timestamp, value)
+            }
+        };
+        self.previous_timestamp = timestamp;
+        self.reader.values += 1;
+        Ok(Some((timestamp, f64::from_bits(value))))
+    }
+}
+
+impl<R: Read> Iterator for GorillaTsDecoder<R> {
+    type Item = Result<(u64, f64), GorillaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_point().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        let mut bytes = Vec::new();
+        gorilla_encode(values, &mut bytes).unwrap();
+        let mut decoded = Vec::new();
+        gorilla_decode(&bytes, &mut decoded).unwrap();
+        decoded
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    #[test]
+    fn round_trips_xors_with_32_or_more_leading_zeros() {
+        for values in [
+            vec![1.0, 1.0 + f64::EPSILON, 1.0],
+            vec![0.30000000000000004, 0.3],
+        ] {
+            assert_bits_eq(&values, &round_trip(&values));
+        }
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = [
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_0001),
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            1.5,
+        ];
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_timestamps_with_special_values() {
+        let points = [
+            (1482892260, f64::NAN),
+            (1482892260, -0.0),
+            (1482978660, f64::INFINITY),
+            (1483065060, f64::NEG_INFINITY),
+            (1483065061, 1.0),
+            (1483065062, 1.0 + f64::EPSILON),
+            (1483065063, 0.30000000000000004),
+            (1483065064, 0.3),
+        ];
+        let mut bytes = Vec::new();
+        let mut encoder = GorillaTsEncoder::new(&mut bytes);
+        for &(timestamp, value) in &points {
+            encoder.push(timestamp, value).unwrap();
+        }
+        encoder.finish().unwrap();
+        let decoded: Vec<(u64, f64)> = GorillaTsDecoder::new(&bytes[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded.len(), points.len());
+        for (&(timestamp, value), &(decoded_timestamp, decoded_value)) in
+            points.iter().zip(&decoded)
+        {
+            assert_eq!(timestamp, decoded_timestamp);
+            assert_eq!(value.to_bits(), decoded_value.to_bits());
+        }
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        assert!(matches!(
+            gorilla_encode(&[], &mut Vec::new()),
+            Err(GorillaError::EmptyInput)
+        ));
+        assert!(matches!(
+            gorilla_decode(&[], &mut Vec::new()),
+            Err(GorillaError::EmptyInput)
+        ));
+    }
+
+    #[test]
+    fn reports_every_cut_as_truncated() {
+        let values: Vec<f64> = (0..100).map(|i| (i as f64 * 0.37).sin() * 100.0).collect();
+        let mut bytes = Vec::new();
+        gorilla_encode(&values, &mut bytes).unwrap();
+        for len in 1..bytes.len() {
+            let mut decoded = Vec::new();
+            match gorilla_decode(&bytes[..len], &mut decoded) {
+                Err(GorillaError::TruncatedStream { values }) => assert_eq!(values, decoded.len()),
+                // the end marker is padded to 128 bits, so cutting into the
+                // padding still finds it
+                Ok(()) => assert_bits_eq(&values, &decoded),
+                Err(err) => panic!("cut at {} of {} bytes: {}", len, bytes.len(), err),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_headers_longer_than_64_bits() {
+        let mut bytes = Vec::new();
+        {
+            let mut writer = Writer::new(&mut bytes);
+            writer.write_bits(1.0_f64.to_bits() as u128, 64).unwrap();
+            // a new block of 31 leading zeros and 64 significant bits
+            writer.write_bits(0b11, 2).unwrap();
+            writer.write_bits(31, 5).unwrap();
+            writer.write_bits(63, 6).unwrap();
+            writer.write_bits(0, 64).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let mut decoded = Vec::new();
+        assert!(matches!(
+            gorilla_decode(&bytes, &mut decoded),
+            Err(GorillaError::InvalidHeader {
+                value: 1,
+                leading_zeros: 31,
+                significant_bits: 64,
+            })
+        ));
+        assert_eq!(decoded, [1.0]);
+    }
+}
@@ -1,1123 +1,454 @@
-use byteorder::{ByteOrder, LittleEndian};
-use core::panic;
+use clap::Parser;
+#[cfg(feature = "sisu")]
 use data as sisu_data;
-use lzzzz::{lz4, lz4_hc, lz4f};
-use sisu_data::{Page, PageBuilder};
-use std::fs::File;
+#[cfg(feature = "sisu")]
+use sisu_data::Page;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
 use std::io::{self, prelude::*, BufReader, BufWriter, Write};
-use std::num;
+#[cfg(feature = "sisu")]
 use std::ops::Add;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sisu")]
 use std::time::{Duration, Instant};
 use std::vec::Vec;
-use tsz::decode::Error as TszError;
-use tsz::stream::{BufferedReader, BufferedWriter};
 
-use tsz::{DataPoint, Decode, Encode, StdDecoder, StdEncoder};
+mod alp;
+mod bench;
+mod byte_stream_split;
+mod chimp;
+mod chunk;
+mod cli;
+mod codec;
+mod container;
+mod decimal;
+mod dictionary;
+mod elf;
+mod fpc;
+mod fpzip;
+mod gorilla;
+mod patas;
+mod pipeline;
+mod registry;
+mod report;
+mod shuffle;
+mod stats;
+mod verify;
+mod zfp;
+
+use cli::{Cli, Command};
 
 extern crate blosc;
-
-const SET: &str = "floats";
-const DATA: &str = "floats.txt";
-const PATH: &str = "./data/floats.txt";
-
-const PAGE_BYTES: u32 = 65535;
+extern crate blosc_sys;
 
 fn main() -> io::Result<()> {
+    match Cli::parse().command {
+        Command::Bench {
+            inputs,
+            column,
+            mut codecs,
+            config,
+            out_dir,
+            chunking,
+            warmup,
+            repetitions,
+            tolerance,
+        } => {
+            if let Some(config) = config {
+                codecs.extend(read_specs(&config)?);
+            }
+            if codecs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no codecs given, use --codec or --config",
+                ));
+            }
+            if repetitions == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--repetitions must be at least 1",
+                ));
+            }
+            let mut chunkings = Vec::new();
+            for spec in &chunking {
+                chunkings.extend(chunk::parse(spec)?);
+            }
+            if chunkings.is_empty() {
+                chunkings.push(chunk::Chunking::default());
+            }
+            let options = bench::Options {
+                warmup,
+                repetitions,
+                tolerance,
+            };
+            for input in &inputs {
+                bench_file(
+                    input,
+                    &column,
+                    &codecs,
+                    &chunkings,
+                    out_dir.as_deref(),
+                    &options,
+                )?;
+            }
+            Ok(())
+        }
+        Command::Compress {
+            input,
+            output,
+            codec,
+            chunking,
+        } => {
+            let chunking = match chunking {
+                Some(spec) => chunk::parse_one(&spec)?,
+                None => chunk::Chunking::default(),
+            };
+            compress_file(&input, &output, &codec, chunking)
+        }
+        Command::Decompress { input, output } => decompress_file(&input, &output),
+        Command::Inspect { input } => inspect_file(&input),
+        Command::Train {
+            inputs,
+            output,
+            sample_values,
+            max_size,
+            split,
+        } => train_dictionary(&inputs, output, sample_values, max_size, split),
+        Command::List => registry::describe(&mut io::stdout()),
+    }
+}
+
+// reads a file with one float per line into an in-memory vector
+fn read_floats(path: &Path) -> io::Result<Vec<f64>> {
     // prepare to buffer-read the file
-    let file = File::open(PATH).unwrap();
+    let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     // read line-by-line and move all floats into an in-memory vector
     let mut vec: Vec<f64> = Vec::new();
-    for line in reader.lines() {
-        let num: f64 = line.unwrap().parse().unwrap();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let num: f64 = line.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: not a float: {:?}", path.display(), i + 1, line),
+            )
+        })?;
         vec.push(num);
     }
-    // let num_floats = vec.len();
-
-    // // convert the data to bytes
-    // let mut buf = vec![0_u8; vec.len() * 8];
-    // LittleEndian::write_f64_into(&vec, &mut buf);
-    // let num_bytes = buf.len();
-
-    // // // bitshuffle
-    // let dataset = hdf5::File::create("hello.h5")
-    //     .unwrap()
-    //     .as_dataset()
-    //     .unwrap().filters().;
-
-    // let group = hdf5::Group::create_group(&self, name);
-    // let builder = hdf5::DatasetBuilder::new(buf);
-
-    // // --------------------------------- //
-    // // TEST DIFFERENT CODEC METHODS HERE //
-    // // --------------------------------- //
-
-    // // q_compress
-    // // https://crates.io/crates/q_compress
-    // // https://github.com/mwlon/quantile-compression
-    // test_q_compress(&vec);
-    // println!("q_compress test done");
-
-    // // zstd
-    // // https://docs.rs/zstd/latest/zstd/
-    // // https://github.com/gyscos/zstd-rs
-    // test_zstd(&vec);
-    // println!("zstd test done");
-
-    // // tsz
-    // // https://docs.rs/tsz/latest/tsz/
-    // // https://github.com/jeromefroe/tsz-rs
-    // test_tsz(&vec);
-    // println!("tsz test done");
-
-    // // snap
-    // // https://lib.rs/crates/snap
-    // test_snap(&vec);
-    // println!("snap test done");
-
-    // // // zfp
-    // // // https://crates.io/crates/zfp-sys
-    // // test_zfp(&mut vec, num_floats, num_bytes);
-    // // println!("zfp test done");
-
-    // lz4
-    test_lzzzz_bytes(&vec);
-    println!("lzzzz test done");
-    test_lzzzz(&vec);
-
-    // // gorilla
-    // test_gorilla(&vec);
-
-    // // baseline
-    // test_baseline(&vec);
-    // println!("baseline test done");
-
-    // blosc
-    test_blosc(&vec);
-    println!("blosc test done");
-
-    Ok(())
+    Ok(vec)
 }
 
-fn test_blosc(vec_total: &[f64]) {
-    // results file
-    let results_path = format!("results/{}/blosc_lz4_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // let usize_from_typesize = |ts: num::NonZeroU8| usize::from(ts.get());
-        let ctx0 = blosc::Context::new().shuffle(blosc::ShuffleMode::Bit);
-        let ctx = ctx0.compressor(blosc::Compressor::LZ4).unwrap();
-        let buffer = ctx.compress(vec);
-
-        // let compressed: Vec<u8> = blosc::Context::new()
-        //     // .shuffle(blosc::ShuffleMode::Bit)
-        //     .compressor(blosc::Compressor::LZ4HC)
-        //     .unwrap()
-        //     .compress(vec)
-        //     .into();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // decompression
-        let decompressed: Vec<f64> = unsafe {
-            // Sadly, decompressing with Blosc is unsafe until
-            // https://github.com/Blosc/c-blosc/issues/229 gets fixed
-            blosc::decompress(&buffer)
-        }
-        .unwrap();
-        assert_eq!(vec, decompressed);
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec.len() as f64) * 8.0 / (buffer.size() as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
+// reads either one float per line or, for `.csv` files, one column of a table
+// with a `Date` column like `data/HistoricalData_1654792445080.csv`, returning
+// the values along with their timestamps in seconds, sorted by date
+fn read_input(path: &Path, column: &str) -> io::Result<(Vec<f64>, Option<Vec<u64>>)> {
+    if path.extension().is_none_or(|ext| ext != "csv") {
+        return Ok((read_floats(path)?, None));
     }
 
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to blosc results file failed");
-    println!("blosc compression done");
-}
-
-// either runs successfully and prints evaluation results or panics
-fn test_q_compress(vec_total: &[f64]) {
-    let results_path = format!("results/{}/q_compress_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-    let mut compression_level = 0;
-
-    while compression_level <= 12 {
-        println!(
-            "q_compress: compression level {} starting",
-            compression_level
-        );
-
-        // stuff that gets updated on each chunk of data
-        let mut total_encoding_time: Duration = Duration::ZERO;
-        let mut total_decoding_time: Duration = Duration::ZERO;
-        let mut total_compression_ratio = 0.0;
-        let mut head = vec_total;
-        let mut done = false;
-        let mut chunks = 0;
-
-        // loop that benchmarks the algorithm on each chunk of data
-        while !done {
-            let vec: &[f64];
-            if (PAGE_BYTES / 8) as usize > head.len() {
-                vec = head;
-                done = true;
-            } else {
-                (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-            }
-
-            // initialize the timer
-            let timer = Instant::now();
-
-            // specify the most efficient compression level / granularity depends on the data
-            let bytes: Vec<u8> = q_compress::auto_compress(vec, compression_level);
-            let compressed_num_bytes: usize = bytes.len();
-
-            // record encoding speed
-            let encoding_speed: Duration = timer.elapsed();
-
-            // decompress and ensure that we encoded and decoded the same number of floating point values
-            let recovered: Vec<f64> =
-                q_compress::auto_decompress::<f64>(&bytes).expect("failed to decompress");
-            let decompressed_num_floats = recovered.len();
-            assert!(vec.len() == decompressed_num_floats);
-
-            // record decoding speed
-            let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-            // record compression ratio
-            let compression_ratio = (vec.len() as f64) * 8.0 / (compressed_num_bytes as f64);
-
-            total_encoding_time = total_encoding_time.add(encoding_speed);
-            total_decoding_time = total_decoding_time.add(decoding_speed);
-            total_compression_ratio += compression_ratio;
-            chunks += 1;
-        }
-
-        // write results to file
-        let results = format!(
-            "Compression level: {}\n\
-            Average Compression ratio: {}\n\
-            Average Encoding speed: {:?}\n\
-            Average Decoding speed: {:?}\n\
-            Total Encoding speed: {:?}\n\
-            Total Decoding speed: {:?}\n\n",
-            compression_level,
-            total_compression_ratio / chunks as f64,
-            total_encoding_time.div_f64(chunks as f64),
-            total_decoding_time.div_f64(chunks as f64),
-            total_encoding_time,
-            total_decoding_time,
-        );
-        write!(results_file, "{}", results).expect("write to q_compress results file failed");
-        println!("q_compress: compression level {} done", compression_level);
-        compression_level += 1;
+    let invalid = |line: usize, msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line, msg),
+        )
+    };
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    let position = |name: &str| {
+        header
+            .split(',')
+            .position(|field| field.trim() == name)
+            .ok_or_else(|| invalid(1, format!("no column named {:?}", name)))
+    };
+    let (date_column, value_column) = (position("Date")?, position(column)?);
+
+    let mut rows = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let field = |column: usize| fields.get(column).copied().unwrap_or("");
+        let date = field(date_column);
+        let timestamp = parse_date(date)
+            .ok_or_else(|| invalid(i + 2, format!("not a MM/DD/YYYY date: {:?}", date)))?;
+        let value = field(value_column);
+        let num: f64 = value
+            .trim_start_matches('$')
+            .parse()
+            .map_err(|_| invalid(i + 2, format!("not a float: {:?}", value)))?;
+        rows.push((timestamp, num));
     }
+    // the file lists the newest day first
+    rows.sort_by_key(|&(timestamp, _)| timestamp);
+    let (timestamps, values) = rows.into_iter().unzip();
+    Ok((values, Some(timestamps)))
 }
 
-fn test_zstd(vec_total: &[f64]) {
-    fn compress(data: &[f64], level: i32) -> Vec<u8> {
-        // convert the data to bytes
-        let mut buf = vec![0_u8; data.len() * 8];
-        LittleEndian::write_f64_into(data, &mut buf);
-        let mut data_bytes = bytebuffer::ByteBuffer::from_bytes(&buf);
-
-        // compress
-        let mut encoder = zstd::stream::Encoder::new(Vec::new(), level).unwrap();
-        io::copy(&mut data_bytes, &mut encoder).unwrap();
-        encoder.finish().unwrap()
+// seconds since the unix epoch at midnight UTC of a MM/DD/YYYY date
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.split('/').map(|part| part.parse::<i64>().ok());
+    let (month, day, year) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
     }
-    fn decompress(vec: &[u8]) -> Vec<f64> {
-        // convert the byte vector into a buffer
-        let compressed_bytes = bytebuffer::ByteBuffer::from_bytes(vec);
-
-        // decompress
-        let mut decompressed_bytes = Vec::new();
-        zstd::stream::copy_decode(compressed_bytes, &mut decompressed_bytes).unwrap();
-
-        // convert bytes to f64
-        let mut decompressed_floats = vec![0_f64; decompressed_bytes.len() / 8];
-        LittleEndian::read_f64_into(&decompressed_bytes, &mut decompressed_floats);
-        decompressed_floats
-    }
-
-    // initialize the results file
-    let results_path = format!("results/{}/zstd_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // # define MINCLEVEL  -99
-    // # define MAXCLEVEL   22
-    let mut compression_level = -10;
-    while compression_level <= 10 {
-        // 22 is max it takes too long
-        // stuff that gets updated on each chunk of data
-        let mut total_encoding_time: Duration = Duration::ZERO;
-        let mut total_decoding_time: Duration = Duration::ZERO;
-        let mut total_compression_ratio = 0.0;
-        let mut head = vec_total;
-        let mut done = false;
-        let mut chunks = 0;
-
-        // loop that benchmarks the algorithm on each chunk of data
-        while !done {
-            let vec: &[f64];
-            if (PAGE_BYTES / 8) as usize > head.len() {
-                vec = head;
-                done = true;
-            } else {
-                (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-            }
-
-            println!("zstd: compression level {} starting", compression_level);
-
-            // initialize the timer
-            let timer = Instant::now();
-
-            // specify the most efficient compression level / granularity depends on the data
-            let compressed_vec = compress(vec, compression_level);
-            let compressed_num_bytes = compressed_vec.len();
-
-            // record encoding speed
-            let encoding_speed: Duration = timer.elapsed();
-
-            // decompress and ensure that we encoded and decoded the same number of floating point values
-            let decompressed = decompress(&compressed_vec);
-            let decompressed_num_floats = decompressed.len();
-            assert!(vec.len() == decompressed_num_floats);
-
-            // record compression ratio
-            let compression_ratio = (vec.len() as f64) * 8.0 / (compressed_num_bytes as f64);
-
-            // record decoding speed
-            let decoding_speed: Duration = timer.elapsed() - encoding_speed;
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u64::try_from(days * 86400).ok()
+}
 
-            total_encoding_time = total_encoding_time.add(encoding_speed);
-            total_decoding_time = total_decoding_time.add(decoding_speed);
-            total_compression_ratio += compression_ratio;
-            chunks += 1;
+// reads one codec spec per line, skipping blank lines and `#` comments
+fn read_specs(path: &Path) -> io::Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut specs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let spec = line.split('#').next().unwrap_or("").trim();
+        if !spec.is_empty() {
+            specs.push(spec.to_string());
         }
-
-        let results = format!(
-            "Compression level: {}\n\
-            Average Compression ratio: {}\n\
-            Average Encoding speed: {:?}\n\
-            Average Decoding speed: {:?}\n\
-            Total Encoding speed: {:?}\n\
-            Total Decoding speed: {:?}\n\n",
-            compression_level,
-            total_compression_ratio / chunks as f64,
-            total_encoding_time.div_f64(chunks as f64),
-            total_decoding_time.div_f64(chunks as f64),
-            total_encoding_time,
-            total_decoding_time,
-        );
-        write!(results_file, "{}", results).expect("write to zstd results file failed");
-        println!("zstd: compression level {} done", compression_level);
-        compression_level += 1;
     }
+    Ok(specs)
 }
 
-fn test_tsz(vec_total: &[f64]) {
-    println!("tsz compression starting");
-
-    // initialize the results file
-    let results_path = format!("results/{}/tsz_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        // convert data to datapoints
-        let default_timestamp: u64 = 1482892260;
-        let w = BufferedWriter::new();
-        let mut encoder = StdEncoder::new(default_timestamp, w);
-        let mut datapoints = Vec::new();
-
-        for num in vec {
-            let dp = DataPoint::new(default_timestamp, *num);
-            datapoints.push(dp);
-        }
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // compress
-        for dp in &datapoints {
-            encoder.encode(*dp);
+// benchmarks every configuration of every spec on one input file, writing one
+// set of results files per codec name, e.g. `results/high/zstd_high.txt`,
+// `results/high/zstd_high.jsonl` and `results/high/zstd_high.csv`, and a row per
+// configuration to `results/high/summary_high.csv`
+fn bench_file(
+    input: &Path,
+    column: &str,
+    specs: &[String],
+    chunkings: &[chunk::Chunking],
+    out_dir: Option<&Path>,
+    options: &bench::Options,
+) -> io::Result<()> {
+    let (vec, timestamps) = read_input(input, column)?;
+
+    let mut data = input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let set = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // tables get results per column, e.g. `zstd_HistoricalData_1654792445080_close_last.txt`
+    let mut stem = set.clone();
+    if timestamps.is_some() {
+        data = format!("{}:{}", data, column);
+        let column: String = column
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        stem = format!("{}_{}", set, column);
+    }
+    let out_dir = match out_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("results").join(&set),
+    };
+    fs::create_dir_all(&out_dir)?;
+
+    let machine = report::Machine::detect();
+    let mut reports: HashMap<String, report::Reports> = HashMap::new();
+    let mut summary = report::SummaryTable::create(&out_dir, &data, &stem)?;
+    for spec in specs {
+        if spec == "baseline" {
+            test_baseline(&vec, &out_dir.join(format!("baseline_{}.txt", stem)))?;
+            continue;
         }
-        let bytes = encoder.close();
-        let compressed_num_bytes = bytes.len();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
 
-        // decode
-        let r = BufferedReader::new(bytes);
-        let mut decoder = StdDecoder::new(r);
-        let mut expected_datapoints = Vec::new();
-        let mut done = false;
-        loop {
-            if done {
-                break;
-            }
-            match decoder.next() {
-                Ok(dp) => expected_datapoints.push(dp),
-                Err(err) => {
-                    if err == TszError::EndOfStream {
-                        done = true;
-                    } else {
-                        panic!("Received an error from decoder: {:?}", err);
-                    }
+        for config in registry::parse(spec, true)? {
+            let codec = config.build()?;
+            let codec_name = codec.name();
+            let reports = match reports.get_mut(&codec_name) {
+                Some(reports) => reports,
+                None => {
+                    let created = report::Reports::create(
+                        &out_dir,
+                        &codec_name,
+                        &data,
+                        &stem,
+                        machine.clone(),
+                    )?;
+                    reports.entry(codec_name).or_insert(created)
                 }
             };
+            for &chunking in chunkings {
+                let result = bench::run(
+                    codec.as_ref(),
+                    &vec,
+                    timestamps.as_deref(),
+                    chunking,
+                    options,
+                )?;
+                reports.write(&result)?;
+                summary.write(&result)?;
+            }
         }
-        assert!(expected_datapoints.len() == vec.len());
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (datapoints.len() as f64 * 8.0) / (compressed_num_bytes as f64);
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
-    }
-
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to tsz results file failed");
-    println!("tsz compression done");
-}
-
-fn test_snap(vec_total: &[f64]) {
-    fn compress(uncompressed: &[u8], compressed: &mut Vec<u8>) -> io::Result<()> {
-        compressed.clear();
-        let mut encoder = snap::write::FrameEncoder::new(compressed);
-        encoder
-            .write_all(uncompressed)
-            .expect("snappy compression failed");
-        encoder.flush().expect("could not flush snappy encoder");
-        Ok(())
+        println!("{} test done", spec);
     }
 
-    fn decompress(compressed: &[u8], decompressed: &mut Vec<u8>) -> io::Result<()> {
-        decompressed.clear();
-        snap::read::FrameDecoder::new(compressed)
-            .read_to_end(decompressed)
-            .expect("snappy decompression failed");
-        Ok(())
+    for reports in reports.values_mut() {
+        reports.flush()?;
     }
+    summary.flush()
+}
 
-    // initialize the results file
-    let results_path = format!("results/{}/snap_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    println!("snap compression starting");
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
+fn compress_file(
+    input: &Path,
+    output: &Path,
+    spec: &str,
+    chunking: chunk::Chunking,
+) -> io::Result<()> {
+    let vec_total = read_floats(input)?;
+    let config = registry::parse_one(spec)?;
+    let codec = config.build()?;
 
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
+    let mut pages = Vec::new();
+    for range in chunking.split(codec.as_ref(), &vec_total)? {
+        let offset = range.start;
+        let vec = &vec_total[range];
+        let bytes = codec.encode(vec)?;
+        // refuse to write a file that would not decompress to the input
+        let decoded = codec.decode(&bytes)?;
+        if codec.lossy() {
+            verify::same_len(&codec.name(), offset, vec, &decoded)?;
         } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
+            verify::bit_exact(&codec.name(), offset, vec, &decoded)?;
         }
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // convert data to bytes and encode it
-        let mut data_bytes = vec![0_u8; vec.len() * 8];
-        LittleEndian::write_f64_into(vec, &mut data_bytes);
-
-        // compress
-        let mut compressed_bytes: Vec<u8> = Vec::new();
-        compress(&data_bytes, &mut compressed_bytes).expect("could not compress with snappy");
-        let compressed_num_bytes = compressed_bytes.len();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // decompress
-        let mut decompressed_bytes = Vec::new();
-        decompress(&compressed_bytes, &mut decompressed_bytes)
-            .expect("could not decompress with snappy");
-
-        // convert to floats
-        let mut decompressed_floats = vec![0_f64; decompressed_bytes.len() / 8];
-        LittleEndian::read_f64_into(&decompressed_bytes, &mut decompressed_floats);
-
-        // ensure that we encoded and decoded the same number of floating point values
-        let decompressed_num_floats = decompressed_floats.len();
-        assert!(vec.len() == decompressed_num_floats);
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (data_bytes.len() as f64) / (compressed_num_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
+        pages.push(container::Page {
+            num_values: container::field_len(vec.len(), "page value count")?,
+            bytes,
+        });
     }
 
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to snap results file failed");
-    println!("snap compression  done");
+    // the files the spec names go into the output, so that it decompresses
+    // wherever it is moved
+    let header = container::Header {
+        spec: config.to_string(),
+        files: config
+            .params
+            .files()
+            .iter()
+            .map(|(path, contents)| (path.clone(), contents.to_vec()))
+            .collect(),
+    };
+    let mut output = BufWriter::new(File::create(output)?);
+    container::write(&mut output, &header, &pages)?;
+    output.flush()
 }
 
-// fn test_zfp(vec_total: &mut [f64], og_num_floats: usize, og_num_bytes: usize) {
-//     println!("zfp compression starting");
-
-//     // stuff that gets updated on each chunk of data
-//     let mut total_encoding_time = 0;
-//     let mut total_decoding_time = 0;
-//     let mut total_compression_ratio = 0.0;
-//     let mut head = vec_total;
-//     let mut done = false;
-//     let mut chunks = 0;
-
-//     // loop that benchmarks the algorithm on each chunk of data
-//     while !done {
-//         let vec: &[f64];
-//         if (PAGE_BYTES / 8) as usize > head.len() {
-//             vec = head;
-//             done = true;
-//         } else {
-//             (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-//         }
-
-//         let mut vec_clone = vec![0_f64; vec.len()];
-//         vec_clone.clone_from_slice(vec);
-
-//         /* allocate meta data for the data vector of floats */
-//         let data_type = zfp_sys::zfp_type_zfp_type_double;
-//         let len = vec.len();
-//         let field = unsafe {
-//             zfp_sys::zfp_field_1d(
-//                 vec.as_mut_ptr() as *mut std::ffi::c_void,
-//                 data_type,
-//                 len as u32,
-//             )
-//         };
-
-//         /* allocate meta data for a compressed stream */
-//         let zfp =
-//             unsafe { zfp_sys::zfp_stream_open(std::ptr::null_mut() as *mut zfp_sys::bitstream) };
-
-//         /* set compression mode and parameters */
-//         // unsafe { zfp_sys::zfp_stream_set_rate(zfp, 8.0, data_type, 1, 0) };
-//         // Compression ratio: 7.99999949257665
-//         // Encoding speed: 292.536271ms
-//         // Decoding speed: 282.665824ms
-//         unsafe { zfp_sys::zfp_stream_set_reversible(zfp) };
-
-//         /* allocate buffer for compressed data */
-//         let bufsize = unsafe { zfp_sys::zfp_stream_maximum_size(zfp, field) };
-//         let mut buffer: Vec<u8> = vec![0; bufsize as usize];
-
-//         /* associate bit stream with allocated buffer */
-//         let stream =
-//             unsafe { zfp_sys::stream_open(buffer.as_mut_ptr() as *mut std::ffi::c_void, bufsize) };
-//         unsafe {
-//             zfp_sys::zfp_stream_set_bit_stream(zfp, stream);
-//             zfp_sys::zfp_stream_rewind(zfp);
-//         }
-
-//         // initialize the timer
-//         let timer = Instant::now();
-
-//         /* compress */
-//         let compressed_num_bytes = unsafe { zfp_sys::zfp_compress(zfp, field) };
-//         if compressed_num_bytes == 0 {
-//             panic!("compression failed");
-//         } else {
-//             let original_size = len * std::mem::size_of::<f64>();
-//             let ratio = (original_size as f64) / (compressed_num_bytes as f64);
-//             println!(
-//                 "bufsize: {} bytes, original size: {} bytes, compressed size: {} bytes, ratio: {}",
-//                 bufsize, original_size, compressed_num_bytes, ratio
-//             );
-//         }
-
-//         // record encoding speed
-//         let encoding_speed: Duration = timer.elapsed();
-
-//         /* decompress */
-//         unsafe { zfp_sys::zfp_stream_rewind(zfp) };
-//         let compressed_size = unsafe { zfp_sys::zfp_decompress(zfp, field) };
-//         if compressed_size == 0 {
-//             panic!("decompression failed");
-//         } else {
-//             println!("ret: {}", compressed_size);
-//         }
-
-//         // record decoding speed
-//         let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-//         // record compression ratio
-//         let compression_ratio = (vec.len() as f64) / (compressed_num_bytes as f64);
-
-//         /* clean up */
-//         unsafe {
-//             zfp_sys::zfp_field_free(field);
-//             zfp_sys::zfp_stream_close(zfp);
-//             zfp_sys::stream_close(stream);
-//         }
-
-//         total_encoding_time += encoding_speed.as_micros();
-//         total_decoding_time += decoding_speed.as_micros();
-//         total_compression_ratio += compression_ratio;
-//         chunks += 1;
-//     }
-
-//     let results_path = format!("results/{}/zfp_{}", SET, DATA);
-//     let results_file = File::create(results_path).unwrap();
-//     let mut results_file = BufWriter::new(results_file);
-
-//     // write results to file
-//     let results = format!(
-//         "Average Compression ratio: {}\n\
-//         Average Encoding speed: {:?}\n\
-//         Average Decoding speed: {:?}\n\n",
-//         total_compression_ratio / chunks as f64,
-//         Duration::from_micros((total_encoding_time as f64 / chunks as f64) as u64),
-//         Duration::from_micros((total_decoding_time as f64 / chunks as f64) as u64),
-//     );
-//     write!(results_file, "{}", results).expect("write to zfp results file failed");
-//     println!("zfp compression done");
-// }
-
-fn test_lzzzz(vec_total: &[f64]) {
-    // results file
-    let results_path = format!("results/{}/lzzzz_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
+fn decompress_file(input: &Path, output: &Path) -> io::Result<()> {
+    let (header, pages) = container::read(&mut BufReader::new(File::open(input)?))?;
+    let files: registry::Files = header
+        .files
+        .into_iter()
+        .map(|(path, contents)| (path, contents.into()))
+        .collect();
+    let codec = registry::parse_stored(&header.spec, &files)?.build()?;
+
+    let mut output = BufWriter::new(File::create(output)?);
+    for page in &pages {
+        let values = codec.decode(&page.bytes)?;
+        if values.len() != page.num_values as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "page decoded to {} values, expected {}",
+                    values.len(),
+                    page.num_values
+                ),
+            ));
         }
-
-        // convert the data to bytes
-        let mut vec_bytes = vec![0_u8; vec.len() * 8];
-        LittleEndian::write_f64_into(vec, &mut vec_bytes);
-        let num_bytes = vec_bytes.len();
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // LZ4 compression
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4::compress_to_vec(&vec_bytes, &mut comp_bytes, lz4::ACC_LEVEL_DEFAULT).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4/LZ4_HC decompression
-        let mut decomp_bytes = vec![0_u8; vec.len() * 8];
-        lz4::decompress(&comp_bytes, &mut decomp_bytes).unwrap();
-        let mut decomp_floats = vec![0_f64; decomp_bytes.len() / 8];
-        LittleEndian::read_f64_into(&decomp_bytes, &mut decomp_floats);
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (num_bytes as f64) / (num_compressed_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
-    }
-
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lz4 compression done");
-
-    // ================================================================================
-
-    // stuff that gets updated on each chunk of data
-    total_encoding_time = Duration::ZERO;
-    total_decoding_time = Duration::ZERO;
-    total_compression_ratio = 0.0;
-    head = vec_total;
-    done = false;
-    chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        // initialize the timer
-        let timer = Instant::now();
-
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
+        for value in values {
+            writeln!(output, "{}", value)?;
         }
-
-        let mut vec_bytes = vec![0_u8; vec.len() * 8];
-        LittleEndian::write_f64_into(vec, &mut vec_bytes);
-
-        // LZ4_HC compression
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4_hc::compress_to_vec(&vec_bytes, &mut comp_bytes, lz4_hc::CLEVEL_DEFAULT).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4/LZ4_HC decompression
-        let mut decomp_bytes = vec![0_u8; vec.len() * 8];
-        lz4::decompress(&comp_bytes, &mut decomp_bytes).unwrap();
-        let mut decomp_floats = vec![0_f64; decomp_bytes.len() / 8];
-        LittleEndian::read_f64_into(&decomp_bytes, &mut decomp_floats);
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec_bytes.len() as f64) / (num_compressed_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
     }
+    output.flush()
+}
 
-    // write results to file
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lzzzz compression LZ4_HZ done");
-
-    // ================================================================================
-
-    // stuff that gets updated on each chunk of data
-    total_encoding_time = Duration::ZERO;
-    total_decoding_time = Duration::ZERO;
-    total_compression_ratio = 0.0;
-    head = vec_total;
-    done = false;
-    chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        let mut vec_bytes = vec![0_u8; vec.len() * 8];
-        LittleEndian::write_f64_into(vec, &mut vec_bytes);
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // LZ4F compression
-        let prefs = lz4f::Preferences::default();
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4f::compress_to_vec(&vec_bytes, &mut comp_bytes, &prefs).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4F decompression
-        let mut decomp_bytes = Vec::new();
-        lz4f::decompress_to_vec(&comp_bytes, &mut decomp_bytes).unwrap();
-        let mut decomp_floats = vec![0_f64; decomp_bytes.len() / 8];
-        LittleEndian::read_f64_into(&decomp_bytes, &mut decomp_floats);
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec_bytes.len() as f64) / (num_compressed_bytes as f64);
+fn inspect_file(input: &Path) -> io::Result<()> {
+    let (header, pages) = container::read(&mut BufReader::new(File::open(input)?))?;
+    let num_values: u64 = pages.iter().map(|page| page.num_values as u64).sum();
+    let num_bytes: u64 = pages.iter().map(|page| page.bytes.len() as u64).sum();
 
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
+    println!("Codec: {}", header.spec);
+    for (path, contents) in &header.files {
+        println!("Stored file: {} ({} bytes)", path, contents.len());
     }
-
-    // write results to file
-    let results = format!(
-        " Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
+    println!("Pages: {}", pages.len());
+    println!("Values: {}", num_values);
+    println!("Compressed bytes: {}", num_bytes);
+    println!(
+        "Compression ratio: {}",
+        (num_values * 8) as f64 / num_bytes as f64
     );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lzzzz compression LZ4F done");
+    Ok(())
 }
 
-fn test_lzzzz_bytes(vec_total: &[f64]) {
-    // results file
-    let results_path = format!("results/{}/lzzzz_bytes_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        let vec_bytes: Vec<u8> = blosc::Context::new()
-            .shuffle(blosc::ShuffleMode::Bit)
-            .compressor(blosc::Compressor::LZ4)
-            .unwrap()
-            // .clevel(blosc::Clevel::L1)
-            .compress(vec)
-            .into();
-
-        // LZ4 compression
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4::compress_to_vec(&vec_bytes, &mut comp_bytes, lz4::ACC_LEVEL_DEFAULT).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4/LZ4_HC decompression
-        let mut decomp_bytes = vec![0_u8; vec_bytes.len()];
-        lz4::decompress(&comp_bytes, &mut decomp_bytes).unwrap();
-        unsafe {
-            let decompressed: Vec<f64> = blosc::decompress_bytes(&decomp_bytes).unwrap();
-        }
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec.len() as f64) * 8.0 / (num_compressed_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
+// trains on the values of every input in turn and writes the dictionary next to
+// the results of the first one unless told otherwise
+fn train_dictionary(
+    inputs: &[PathBuf],
+    output: Option<PathBuf>,
+    sample_values: usize,
+    max_size: usize,
+    split: bool,
+) -> io::Result<()> {
+    let mut values = Vec::new();
+    for input in inputs {
+        values.extend(read_floats(input)?);
     }
-
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lz4 compression done");
-
-    // ================================================================================
-
-    // stuff that gets updated on each chunk of data
-    total_encoding_time = Duration::ZERO;
-    total_decoding_time = Duration::ZERO;
-    total_compression_ratio = 0.0;
-    head = vec_total;
-    done = false;
-    chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        // initialize the timer
-        let timer = Instant::now();
-
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        let vec_bytes: Vec<u8> = blosc::Context::new()
-            .shuffle(blosc::ShuffleMode::Bit)
-            .compress(&vec)
-            .into();
-        let num_bytes = vec_bytes.len();
-
-        // LZ4_HC compression
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4_hc::compress_to_vec(&vec_bytes, &mut comp_bytes, lz4_hc::CLEVEL_DEFAULT).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4/LZ4_HC decompression
-        let mut decomp_bytes = vec![0_u8; vec_bytes.len() * 8];
-        lz4::decompress(&comp_bytes, &mut decomp_bytes).unwrap();
-        unsafe {
-            let decompressed: Vec<f64> = blosc::decompress_bytes(&decomp_bytes).unwrap();
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let stem = inputs[0]
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            PathBuf::from("results")
+                .join(&stem)
+                .join(format!("{}.dict", stem))
         }
+    };
 
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec.len() as f64) * 8.0 / (num_compressed_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
+    let dictionary = dictionary::train(&values, sample_values, split, max_size)?;
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
     }
-
-    // write results to file
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
+    fs::write(&output, &dictionary)?;
+    println!(
+        "Trained a {} byte dictionary on {} values in samples of {}, written to {}",
+        dictionary.len(),
+        values.len(),
+        sample_values,
+        output.display()
     );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lzzzz compression LZ4_HZ done");
-
-    // ================================================================================
-
-    // stuff that gets updated on each chunk of data
-    total_encoding_time = Duration::ZERO;
-    total_decoding_time = Duration::ZERO;
-    total_compression_ratio = 0.0;
-    head = vec_total;
-    done = false;
-    chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-
-        let vec_bytes: Vec<u8> = blosc::Context::new()
-            .shuffle(blosc::ShuffleMode::Bit)
-            .compress(&vec)
-            .into();
-        let num_bytes = vec_bytes.len();
-
-        // initialize the timer
-        let timer = Instant::now();
-
-        // LZ4F compression
-        let prefs = lz4f::Preferences::default();
-        let mut comp_bytes = Vec::new();
-        let num_compressed_bytes =
-            lz4f::compress_to_vec(&vec_bytes, &mut comp_bytes, &prefs).unwrap();
-
-        // record encoding speed
-        let encoding_speed: Duration = timer.elapsed();
-
-        // LZ4F decompression
-        let mut decomp_bytes = Vec::new();
-        lz4f::decompress_to_vec(&comp_bytes, &mut decomp_bytes).unwrap();
-        unsafe {
-            let decompressed: Vec<f64> = blosc::decompress_bytes(&decomp_bytes).unwrap();
-        }
-
-        // record decoding speed
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-
-        // record compression ratio
-        let compression_ratio = (vec.len() as f64) * 8.0 / (num_compressed_bytes as f64);
-
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
-    }
+    Ok(())
+}
 
-    // write results to file
-    let results = format!(
-        " Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to lz4 results file failed");
-    println!("lzzzz compression LZ4F done");
+#[cfg(not(feature = "sisu"))]
+fn test_baseline(_vec: &[f64], _results_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "baseline needs the sisu page builder, build with --features sisu",
+    ))
 }
 
-fn test_baseline(vec: &[f64]) {
+#[cfg(feature = "sisu")]
+fn test_baseline(vec: &[f64], results_path: &Path) -> io::Result<()> {
     let mut total_encoding_time: Duration = Duration::ZERO;
     let mut total_decoding_time: Duration = Duration::ZERO;
     let mut chunks = 0;
@@ -1170,211 +501,9 @@ fn test_baseline(vec: &[f64]) {
         total_decoding_time,
     );
     // results file
-    let results_path = format!("results/{}/baseline_{}", SET, DATA);
     let results_file = File::create(results_path).unwrap();
     let mut results_file = BufWriter::new(results_file);
     write!(results_file, "{}", results).expect("write to baseline results file failed");
     println!("baseline compression done");
-}
-
-fn test_gorilla(vec_total: &[f64]) {
-    // results file
-    let results_path = format!("results/{}/gorilla_{}", SET, DATA);
-    let results_file = File::create(results_path).unwrap();
-    let mut results_file = BufWriter::new(results_file);
-
-    // stuff that gets updated on each chunk of data
-    let mut total_encoding_time: Duration = Duration::ZERO;
-    let mut total_decoding_time: Duration = Duration::ZERO;
-    let mut total_compression_ratio = 0.0;
-    let mut head = vec_total;
-    let mut done = false;
-    let mut chunks = 0;
-
-    // loop that benchmarks the algorithm on each chunk of data
-    while !done {
-        let vec: &[f64];
-        if (PAGE_BYTES / 8) as usize > head.len() {
-            vec = head;
-            done = true;
-        } else {
-            (vec, head) = head.split_at((PAGE_BYTES / 8) as usize);
-        }
-        // ------------------------------------
-        let timer = Instant::now();
-        // ------------------------------------
-
-        let mut dst = Vec::new();
-        gorilla_encode(vec, &mut dst);
-        let num_compressed_bytes = dst.len();
-
-        // ------------------------------------
-        let encoding_speed: Duration = timer.elapsed();
-        // ------------------------------------
-
-        let mut decoded: Vec<f64> = Vec::new();
-        gorilla_decode(&dst, &mut decoded);
-
-        // ------------------------------------
-        let decoding_speed: Duration = timer.elapsed() - encoding_speed;
-        // ------------------------------------
-
-        // record compression ratio
-        let compression_ratio = ((vec.len() * 8) as f64) / (num_compressed_bytes as f64);
-
-        // verify got same values back
-        assert_eq!(decoded, vec);
-        total_encoding_time = total_encoding_time.add(encoding_speed);
-        total_decoding_time = total_decoding_time.add(decoding_speed);
-        total_compression_ratio += compression_ratio;
-        chunks += 1;
-    }
-
-    // write results to file
-    let results = format!(
-        "Average Compression ratio: {}\n\
-        Average Encoding speed: {:?}\n\
-        Average Decoding speed: {:?}\n\
-        Total Encoding speed: {:?}\n\
-        Total Decoding speed: {:?}\n\n",
-        total_compression_ratio / chunks as f64,
-        total_encoding_time.div_f64(chunks as f64),
-        total_decoding_time.div_f64(chunks as f64),
-        total_encoding_time,
-        total_decoding_time,
-    );
-    write!(results_file, "{}", results).expect("write to gorilla2 results file failed");
-    println!("gorilla2 compression done");
-}
-
-use bit_streamer::Writer;
-
-pub fn gorilla_encode(src: &[f64], dst: &mut Vec<u8>) {
-    let data = src;
-
-    // Start by making a buffer that writes into a file called input_filename.gorilla
-    let mut writer = Writer::new(dst);
-
-    // First write the first value in full
-    let mut previous_value = data[0].to_bits();
-
-    // Initialize leading and trailing zeros
-    let mut previous_leading_zeros: u32 = 64;
-    let mut previous_trailing_zeros: u32 = 64;
-
-    writer.write_bits(previous_value as u128, 64).unwrap();
-    for &d in &data[1..] {
-        let next_value = d.to_bits();
-        let xor = next_value ^ previous_value;
-
-        // Update previous values for next iteration
-        previous_value = next_value;
-
-        if xor == 0 {
-            // If there is no difference from previous value, then we write a 0
-            writer.write_bit(false).unwrap();
-        } else {
-            // Otherwise a 1, followed by more logic to show difference
-            writer.write_bit(true).unwrap();
-
-            let current_leading_zeros = xor.leading_zeros();
-            let current_trailing_zeros = xor.trailing_zeros();
-
-            // If block of meaningful bits is within previous meaningful bits
-            if current_leading_zeros >= previous_leading_zeros
-                && current_trailing_zeros >= previous_trailing_zeros
-            {
-                // Write a zero control bit followed by meaningful bits
-                writer.write_bit(false).unwrap();
-                writer
-                    .write_bits(
-                        xor.wrapping_shr(previous_trailing_zeros) as u128,
-                        (64 - previous_leading_zeros - previous_trailing_zeros) as usize,
-                    )
-                    .unwrap();
-            } else {
-                // Otherwise, we write a 1 control bit, followed by the 5 bits of the number of
-                // leading zeros, then 6 bits of the number of significant bits
-                // Followed by the significant bits
-                writer.write_bit(true).unwrap();
-                writer.write_bits(current_leading_zeros as u128, 5).unwrap();
-
-                let significant_bits = 64 - current_leading_zeros - current_trailing_zeros;
-                writer
-                    .write_bits((significant_bits - 1) as u128, 6)
-                    .unwrap();
-                writer
-                    .write_bits(
-                        xor.wrapping_shr(current_trailing_zeros) as u128,
-                        significant_bits as usize,
-                    )
-                    .unwrap();
-                previous_trailing_zeros = current_trailing_zeros;
-                previous_leading_zeros = current_leading_zeros;
-            }
-        }
-    }
-
-    // Write an end marker that says it's a new value, with more meaningful bits, 0 leading zeros,
-    // 64 significant values, then a total value of 0. This cannot happen so it's a safe end marker
-    writer
-        .write_bits(0b11_00000_111111 << (128 - 13), 128)
-        .unwrap();
-    writer.flush().unwrap();
-}
-
-use bit_streamer::Reader;
-use std::convert::TryInto;
-
-pub fn gorilla_decode(bytes: &[u8], values: &mut Vec<f64>) {
-    // Initialize
-    let mut leading_zeros: u128 = 0;
-    let mut trailing_zeros: u128 = 0;
-
-    // Initialize reader
-    let mut reader = Reader::new(bytes);
-
-    // Read the first value
-    let mut previous_value = u64::from_be_bytes(
-        reader
-            .read_bytes(8)
-            .unwrap()
-            .try_into()
-            .expect("Read wrong number of bytes"),
-    );
-    values.push(f64::from_bits(previous_value));
-
-    // Now loop through entire file
-    loop {
-        let next_bit: bool;
-        let eof_check = reader.read_bit();
-        if eof_check.is_err() {
-            // Break when we don't read anymore bits
-            break;
-        } else {
-            next_bit = eof_check.unwrap();
-        }
-        if !next_bit {
-            // If next bit is 0, then it's the same value as previously
-            values.push(f64::from_bits(previous_value));
-        } else {
-            // next bit was 1 and there's a difference from last bit
-            if reader.read_bit().unwrap() {
-                // If control bit is 1, we get number of leading zeros from next 5 bits
-                // then the length of meaningful XORed value in the next 6 bits
-                leading_zeros = reader.read_bits(5).unwrap();
-                trailing_zeros = 64 - leading_zeros - (reader.read_bits(6).unwrap() + 1);
-            }
-            let size = 64 - leading_zeros - trailing_zeros;
-            let next_bits = reader.read_bits(size as usize).unwrap() as u64;
-
-            // Check for end marker
-            if leading_zeros == 0 && size == 64 && next_bits == 0 {
-                break;
-            }
-
-            previous_value ^= next_bits << trailing_zeros;
-            values.push(f64::from_bits(previous_value));
-        }
-    }
+    Ok(())
 }
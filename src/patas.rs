@@ -0,0 +1,218 @@
+//! Patas, the byte-aligned variant of Chimp128 from DuckDB.
+//!
+//! Every value is XORed with the best of the previous 128 values, found like in
+//! Chimp128, but the result is written in whole bytes so that decoding needs no
+//! bit reader. After the value count (u32) and the first value (u64), each value
+//! takes a u16 header followed by its significant bytes:
+//!
+//! * bits 0..7: position of the reference value in the ring buffer
+//! * bits 7..10: number of significant bytes minus one
+//! * bits 10..16: trailing zeros of the XOR
+//!
+//! All little endian. A XOR of 0 is written as one zero byte.
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io;
+
+const LOG2_PREVIOUS: u32 = 7;
+const PREVIOUS: usize = 1 << LOG2_PREVIOUS;
+// low bits that have to match for a previous value to become the reference
+const KEY_BITS: u32 = 6 + LOG2_PREVIOUS + 1;
+
+pub fn patas_encode(src: &[f64], dst: &mut Vec<u8>) -> io::Result<()> {
+    let count = u32::try_from(src.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "patas pages hold at most u32::MAX values",
+        )
+    })?;
+    dst.extend_from_slice(&count.to_le_bytes());
+
+    let mut previous = [0_u64; PREVIOUS];
+    // the last position of every key, None until a value with it was seen
+    let mut indices: Vec<Option<usize>> = vec![None; 1 << KEY_BITS];
+    for (i, value) in src.iter().enumerate() {
+        let value = value.to_bits();
+        let key = value as usize & ((1 << KEY_BITS) - 1);
+        if i == 0 {
+            dst.extend_from_slice(&value.to_le_bytes());
+        } else {
+            // the value with the same low bits if it is recent enough, so that
+            // the XOR has at least KEY_BITS trailing zeros
+            let position = match indices[key] {
+                Some(candidate) if i - candidate <= PREVIOUS => candidate % PREVIOUS,
+                _ => (i - 1) % PREVIOUS,
+            };
+            let xor = value ^ previous[position];
+            let (trailing_zeros, significant_bits) = match xor {
+                0 => (0, 0),
+                _ => (
+                    xor.trailing_zeros(),
+                    64 - xor.leading_zeros() - xor.trailing_zeros(),
+                ),
+            };
+            let significant_bytes = significant_bits.div_ceil(8).max(1) as usize;
+
+            let header = position as u16
+                | ((significant_bytes - 1) as u16) << 7
+                | (trailing_zeros as u16) << 10;
+            dst.extend_from_slice(&header.to_le_bytes());
+            dst.extend_from_slice(&(xor >> trailing_zeros).to_le_bytes()[..significant_bytes]);
+        }
+        previous[i % PREVIOUS] = value;
+        indices[key] = Some(i);
+    }
+    Ok(())
+}
+
+pub fn patas_decode(bytes: &[u8]) -> io::Result<Vec<f64>> {
+    let truncated = || {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "patas page ends in the middle of a value",
+        )
+    };
+    if bytes.len() < 4 {
+        return Err(truncated());
+    }
+    let count = LittleEndian::read_u32(bytes) as usize;
+    let mut pos = 4;
+
+    let mut previous = [0_u64; PREVIOUS];
+    let mut values = Vec::new();
+    for i in 0..count {
+        let value = if i == 0 {
+            let first = bytes.get(pos..pos + 8).ok_or_else(truncated)?;
+            pos += 8;
+            LittleEndian::read_u64(first)
+        } else {
+            let header = bytes.get(pos..pos + 2).ok_or_else(truncated)?;
+            let header = LittleEndian::read_u16(header);
+            pos += 2;
+            let position = (header & 0x7f) as usize;
+            let significant_bytes = ((header >> 7) & 0x7) as usize + 1;
+            let trailing_zeros = (header >> 10) as u32;
+
+            let mut xor = [0_u8; 8];
+            xor[..significant_bytes].copy_from_slice(
+                bytes
+                    .get(pos..pos + significant_bytes)
+                    .ok_or_else(truncated)?,
+            );
+            pos += significant_bytes;
+            previous[position] ^ (u64::from_le_bytes(xor) << trailing_zeros)
+        };
+        previous[i % PREVIOUS] = value;
+        values.push(f64::from_bits(value));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(values: &[f64]) -> Vec<f64> {
+        let mut bytes = Vec::new();
+        patas_encode(values, &mut bytes).unwrap();
+        patas_decode(&bytes).unwrap()
+    }
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_values(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect()
+    }
+
+    fn special_values() -> Vec<f64> {
+        vec![
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_single_value() {
+        assert_bits_eq(&[-1.25], &round_trip(&[-1.25]));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let values = special_values();
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_random_bits() {
+        let values = random_values(1000);
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn round_trips_values_repeating_within_and_beyond_the_ring_buffer() {
+        let mut values = Vec::new();
+        for period in [5, 100, 200] {
+            values.extend((0..600).map(|i| ((i % period) as f64 * 0.37).sin() * 100.0));
+        }
+        assert_bits_eq(&values, &round_trip(&values));
+    }
+
+    #[test]
+    fn references_the_previous_value_for_unseen_keys() {
+        // three different keys, the last two values one bit apart
+        let values = [
+            f64::from_bits(0xc000_0000_0000_0001),
+            f64::from_bits(0x3ff0_0000_0000_0002),
+            f64::from_bits(0x3ff0_0000_0000_0003),
+        ];
+        let mut bytes = Vec::new();
+        patas_encode(&values, &mut bytes).unwrap();
+        // count, first value, then 8 significant bytes and 1
+        assert_eq!(bytes.len(), 4 + 8 + (2 + 8) + (2 + 1));
+        let header = LittleEndian::read_u16(&bytes[22..]);
+        assert_eq!(header & 0x7f, 1);
+        assert_bits_eq(&values, &patas_decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn reports_every_cut_as_unexpected_eof() {
+        let values = random_values(20);
+        let mut bytes = Vec::new();
+        patas_encode(&values, &mut bytes).unwrap();
+        for len in 0..bytes.len() {
+            let err = patas_decode(&bytes[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", len);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Reversible transforms that a pipeline applies to a page before handing it to
+//! a codec.
+//!
+//! A pipeline is written as transforms separated by `|` and ending in a codec
+//! spec, e.g. `xor|bitshuffle|zstd:3` or `delta|zigzag|split|none`. Transforms
+//! work on the 64 bit patterns of the values and keep their number, so the
+//! result can be passed on to any lossless codec as floats again; `none` stores
+//! it as is. The transforms run in the prepare phase and are undone in the
+//! finalize phase, so their cost is reported apart from the codec's.
+
+use std::fmt;
+use std::io;
+
+use crate::byte_stream_split::{join_streams, split_streams};
+use crate::shuffle::{bitshuffle, bitunshuffle, byteshuffle, byteunshuffle};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// Difference to the previous value's bits, wrapping.
+    Delta,
+    /// XOR with the previous value's bits.
+    Xor,
+    /// Maps the bits as a signed integer to an unsigned one with small values
+    /// for small magnitudes, usually after `Delta`.
+    ZigZag,
+    /// Byte shuffle of the 8 byte values, see `shuffle.rs`.
+    ByteShuffle,
+    /// Bit shuffle of the 8 byte values, see `shuffle.rs`.
+    BitShuffle,
+    /// Byte shuffle of 4 byte elements, the two halves of every value, as blosc
+    /// would shuffle f32 data.
+    ByteShuffle4,
+    /// Bit shuffle of 4 byte elements.
+    BitShuffle4,
+    /// Parquet's BYTE_STREAM_SPLIT, see `byte_stream_split.rs`. A page is
+    /// shuffled as one block, so this gives the same bytes as `ByteShuffle`.
+    Split,
+}
+
+const TRANSFORMS: &[(&str, Transform)] = &[
+    ("delta", Transform::Delta),
+    ("xor", Transform::Xor),
+    ("zigzag", Transform::ZigZag),
+    ("byteshuffle", Transform::ByteShuffle),
+    ("bitshuffle", Transform::BitShuffle),
+    ("byteshuffle4", Transform::ByteShuffle4),
+    ("bitshuffle4", Transform::BitShuffle4),
+    ("split", Transform::Split),
+];
+
+impl Transform {
+    pub fn parse(name: &str) -> io::Result<Transform> {
+        TRANSFORMS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "unknown transform {}, expected one of: {}",
+                        name,
+                        names().join(", ")
+                    ),
+                )
+            })
+    }
+
+    pub fn name(&self) -> &'static str {
+        TRANSFORMS.iter().find(|(_, t)| t == self).unwrap().0
+    }
+
+    pub fn forward(&self, words: &mut Vec<u64>) {
+        match self {
+            Transform::Delta => {
+                for i in (1..words.len()).rev() {
+                    words[i] = words[i].wrapping_sub(words[i - 1]);
+                }
+            }
+            Transform::Xor => {
+                for i in (1..words.len()).rev() {
+                    words[i] ^= words[i - 1];
+                }
+            }
+            Transform::ZigZag => {
+                for word in words.iter_mut() {
+                    let n = *word as i64;
+                    *word = ((n << 1) ^ (n >> 63)) as u64;
+                }
+            }
+            Transform::ByteShuffle => map_bytes(words, |bytes| byteshuffle(bytes, 8)),
+            Transform::BitShuffle => map_bytes(words, |bytes| bitshuffle(bytes, 8)),
+            Transform::ByteShuffle4 => map_bytes(words, |bytes| byteshuffle(bytes, 4)),
+            Transform::BitShuffle4 => map_bytes(words, |bytes| bitshuffle(bytes, 4)),
+            Transform::Split => map_bytes(words, |bytes| split_streams(bytes, 8)),
+        }
+    }
+
+    pub fn inverse(&self, words: &mut Vec<u64>) -> io::Result<()> {
+        match self {
+            Transform::Delta => {
+                for i in 1..words.len() {
+                    words[i] = words[i].wrapping_add(words[i - 1]);
+                }
+            }
+            Transform::Xor => {
+                for i in 1..words.len() {
+                    words[i] ^= words[i - 1];
+                }
+            }
+            Transform::ZigZag => {
+                for word in words.iter_mut() {
+                    *word = ((*word >> 1) as i64 ^ -((*word & 1) as i64)) as u64;
+                }
+            }
+            Transform::ByteShuffle => map_bytes(words, |bytes| byteunshuffle(bytes, 8)),
+            Transform::BitShuffle => map_bytes(words, |bytes| bitunshuffle(bytes, 8)),
+            Transform::ByteShuffle4 => map_bytes(words, |bytes| byteunshuffle(bytes, 4)),
+            Transform::BitShuffle4 => map_bytes(words, |bytes| bitunshuffle(bytes, 4)),
+            Transform::Split => {
+                let bytes = join_streams(&words_to_bytes(words), 8)?;
+                *words = bytes_to_words(&bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub fn names() -> Vec<&'static str> {
+    TRANSFORMS.iter().map(|(n, _)| *n).collect()
+}
+
+/// Parses the transforms of a pipeline, the part of the spec before the codec.
+pub fn parse(chain: &str) -> io::Result<Vec<Transform>> {
+    chain
+        .split('|')
+        .map(|t| Transform::parse(t.trim()))
+        .collect()
+}
+
+fn words_to_bytes(words: &[u64]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn bytes_to_words(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        })
+        .collect()
+}
+
+// applies a byte transform to the little endian bytes of the words
+fn map_bytes<F: Fn(&[u8]) -> Vec<u8>>(words: &mut Vec<u64>, f: F) {
+    *words = bytes_to_words(&f(&words_to_bytes(words)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry;
+
+    fn assert_bits_eq(expected: &[f64], actual: &[f64]) {
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(expected), bits(actual));
+    }
+
+    // xorshift bits from a fixed seed, so that failures repeat
+    fn random_words(len: usize) -> Vec<u64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            })
+            .collect()
+    }
+
+    fn special_words() -> Vec<u64> {
+        [
+            f64::NAN,
+            -f64::NAN,
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            1.5,
+            // subnormals
+            f64::from_bits(1),
+            f64::from_bits(0x800f_ffff_ffff_ffff),
+            // a signalling NaN
+            f64::from_bits(0x7ff0_0000_0000_0001),
+        ]
+        .iter()
+        .map(|v| v.to_bits())
+        .collect()
+    }
+
+    #[test]
+    fn every_transform_is_undone_by_its_inverse() {
+        // lengths around the groups of 8 and blocks of 16 and 32 of the shuffles
+        let inputs = vec![
+            Vec::new(),
+            vec![0x3ff8_0000_0000_0000],
+            special_words(),
+            random_words(31),
+            random_words(33),
+            random_words(1000),
+        ];
+        for &(name, transform) in TRANSFORMS {
+            for words in &inputs {
+                let mut transformed = words.clone();
+                transform.forward(&mut transformed);
+                assert_eq!(transformed.len(), words.len(), "{}", name);
+                transform.inverse(&mut transformed).unwrap();
+                assert_eq!(&transformed, words, "{} of {} words", name, words.len());
+            }
+        }
+    }
+
+    #[test]
+    fn chains_are_undone_in_reverse_order() {
+        let transforms = parse("delta|zigzag|bitshuffle|split").unwrap();
+        let words = random_words(100);
+        let mut transformed = words.clone();
+        for transform in &transforms {
+            transform.forward(&mut transformed);
+        }
+        for transform in transforms.iter().rev() {
+            transform.inverse(&mut transformed).unwrap();
+        }
+        assert_eq!(transformed, words);
+    }
+
+    #[test]
+    fn parses_every_name_back_to_its_transform() {
+        for &(name, transform) in TRANSFORMS {
+            assert_eq!(Transform::parse(name).unwrap(), transform);
+            assert_eq!(transform.to_string(), name);
+        }
+        assert_eq!(
+            parse(" xor | split ").unwrap(),
+            [Transform::Xor, Transform::Split]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_transforms() {
+        let err = parse("xor|rotate").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("rotate"));
+    }
+
+    #[test]
+    fn passes_timestamps_to_the_codec() {
+        let times = [1654041600, 1654128000, 1654387200, 1654473600];
+        let values = [1.5, 1.75, f64::NAN, -0.0];
+        for spec in ["xor|gorilla_ts", "delta|zigzag|tsz"] {
+            let codec = registry::parse_one(spec).unwrap().build().unwrap();
+            let prepared = codec.prepare_timestamped(&times, &values).unwrap();
+            let bytes = codec.encode_prepared(&prepared).unwrap();
+            let decoded = codec.decode_raw(&bytes).unwrap();
+            let decoded_times: Vec<u64> = decoded
+                .points()
+                .unwrap()
+                .iter()
+                .map(|dp| dp.get_time())
+                .collect();
+            assert_eq!(decoded_times, times, "{}", spec);
+            assert_bits_eq(&values, &codec.finalize(decoded).unwrap());
+        }
+    }
+}
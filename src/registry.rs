@@ -0,0 +1,958 @@
+//! Every codec that can be named on the command line, together with its
+//! tunable parameters.
+//!
+//! A codec is addressed by a spec string: the codec name, optionally followed
+//! by `:` and comma-separated parameters, e.g. `zstd:level=3` or
+//! `blosc:lz4,shuffle=bit`. Parameters given without a name are assigned to the
+//! codec's parameters in order. A value may also be a sweep, either an
+//! inclusive integer range (`level=-10..10`) or alternatives separated by `/`
+//! (`shuffle=none/byte/bit`), in which case the spec expands to one
+//! configuration per combination. File parameters, like the dictionary of
+//! `zstd_dict`, take a path as is, are never swept and are read when the spec
+//! is parsed.
+//!
+//! A spec may also start with transforms separated by `|`, like
+//! `xor|bitshuffle|zstd:3`, which makes a pipeline of those transforms and the
+//! codec, see `pipeline.rs`.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crate::codec::{
+    Alp, Blosc, Chimp, DecimalScaled, Elf, FloatCodec, Fpc, Fpzip, Gorilla, GorillaTs, Lz4,
+    Lz4Mode, Patas, Pipeline, QCompress, Raw, Snap, Tsz, Zfp, Zstd,
+};
+use crate::dictionary::Dictionary;
+use crate::pipeline::{self, Transform};
+use crate::zfp::ZfpMode;
+
+/// The values a parameter accepts.
+#[derive(Clone, Copy, Debug)]
+pub enum ParamKind {
+    /// An integer in `min..=max`
+    Int { min: i64, max: i64 },
+    /// One of a fixed set of names
+    Choice(&'static [&'static str]),
+    /// Path of an existing file, never swept since paths contain `/`
+    File,
+}
+
+const BOOL: ParamKind = ParamKind::Choice(&["false", "true"]);
+
+#[derive(Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: &'static str,
+    pub help: &'static str,
+}
+
+pub struct CodecEntry {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub params: &'static [Param],
+    /// Parameters swept by `bench` when a spec names the codec without any.
+    pub sweep: &'static str,
+    build: fn(&Params) -> io::Result<Box<dyn FloatCodec>>,
+}
+
+/// Validated values for every parameter of a codec, in declaration order,
+/// with the contents of file parameters read when the spec was parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Params {
+    values: Vec<(&'static str, String)>,
+    files: Files,
+}
+
+/// Contents of files named by a spec, keyed by the path as given.
+pub type Files = Vec<(String, Rc<[u8]>)>;
+
+impl Params {
+    fn get(&self, name: &str) -> &str {
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+            .unwrap_or_else(|| panic!("codec has no parameter {}", name))
+    }
+
+    pub fn int(&self, name: &str) -> i64 {
+        // values are validated when the spec is parsed
+        self.get(name).parse().unwrap()
+    }
+
+    pub fn choice(&self, name: &str) -> &str {
+        self.get(name)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.get(name) == "true"
+    }
+
+    /// The path and contents of a file parameter.
+    pub fn file(&self, name: &str) -> (&str, &[u8]) {
+        let path = self.get(name);
+        let contents = self
+            .files
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, contents)| &contents[..])
+            .unwrap_or_else(|| panic!("file {} was not read when parsing", path));
+        (path, contents)
+    }
+
+    /// Every file the parameters name, to store along with their output.
+    pub fn files(&self) -> &Files {
+        &self.files
+    }
+}
+
+/// A codec together with a value for each of its parameters.
+pub struct Config {
+    pub entry: &'static CodecEntry,
+    pub params: Params,
+    /// Transforms applied before the codec, empty unless the spec is a pipeline.
+    pub transforms: Vec<Transform>,
+}
+
+impl Config {
+    /// Fails for combinations of parameters that the codec rejects, which
+    /// `parse` already reports for every configuration.
+    pub fn build(&self) -> io::Result<Box<dyn FloatCodec>> {
+        let codec = (self.entry.build)(&self.params)?;
+        if self.transforms.is_empty() {
+            Ok(codec)
+        } else {
+            Ok(Box::new(Pipeline {
+                transforms: self.transforms.clone(),
+                codec,
+            }))
+        }
+    }
+}
+
+// the canonical spec, which parses back into the same configuration
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for transform in &self.transforms {
+            write!(f, "{}|", transform)?;
+        }
+        write!(f, "{}", self.entry.name)?;
+        for (i, (name, value)) in self.params.values.iter().enumerate() {
+            let sep = if i == 0 { ':' } else { ',' };
+            write!(f, "{}{}={}", sep, name, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+static ENTRIES: &[CodecEntry] = &[
+    CodecEntry {
+        name: "none",
+        help: "the little endian bytes uncompressed, e.g. to end a pipeline",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Raw)),
+    },
+    CodecEntry {
+        name: "blosc",
+        help: "blosc meta-compressor",
+        params: &[
+            Param {
+                name: "compressor",
+                kind: ParamKind::Choice(&["blosclz", "lz4", "lz4hc", "snappy", "zlib", "zstd"]),
+                default: "lz4",
+                help: "backend compressor",
+            },
+            Param {
+                name: "shuffle",
+                kind: ParamKind::Choice(&["none", "byte", "bit"]),
+                default: "bit",
+                help: "shuffle filter applied before compressing",
+            },
+            Param {
+                name: "clevel",
+                kind: ParamKind::Int { min: 0, max: 9 },
+                default: "2",
+                help: "compression level",
+            },
+            Param {
+                name: "typesize",
+                kind: ParamKind::Int { min: 1, max: 255 },
+                default: "8",
+                help: "bytes per element for the shuffle",
+            },
+            Param {
+                name: "nthreads",
+                kind: ParamKind::Int { min: 1, max: 256 },
+                default: "1",
+                help: "threads compressing and decompressing a page",
+            },
+        ],
+        sweep: "compressor=blosclz/lz4/lz4hc/snappy/zlib/zstd,shuffle=none/byte/bit,clevel=0..9,\
+            typesize=1/2/4/8,nthreads=1/2/4",
+        build: |p| {
+            Ok(Box::new(Blosc {
+                compressor: Blosc::compressor_from_name(p.choice("compressor")),
+                shuffle: Blosc::shuffle_from_name(p.choice("shuffle")),
+                clevel: p.int("clevel") as u8,
+                typesize: p.int("typesize") as usize,
+                nthreads: p.int("nthreads") as u32,
+            }))
+        },
+    },
+    CodecEntry {
+        name: "q_compress",
+        help: "quantile compression",
+        params: &[Param {
+            name: "level",
+            kind: ParamKind::Int { min: 0, max: 12 },
+            default: "6",
+            help: "compression level",
+        }],
+        sweep: "level=0..12",
+        build: |p| {
+            Ok(Box::new(QCompress {
+                level: p.int("level") as usize,
+            }))
+        },
+    },
+    CodecEntry {
+        name: "zstd",
+        help: "zstd over the little endian bytes",
+        params: &[
+            Param {
+                name: "level",
+                // ZSTD_minCLevel() is -(1 << 17), ZSTD_maxCLevel() is 22
+                kind: ParamKind::Int {
+                    min: -(1 << 17),
+                    max: 22,
+                },
+                default: "3",
+                help: "compression level",
+            },
+            Param {
+                name: "split",
+                kind: BOOL,
+                default: "false",
+                help: "byte stream split before compressing",
+            },
+        ],
+        // 22 is max it takes too long
+        sweep: "level=-10..10,split=false/true",
+        build: |p| {
+            Ok(Box::new(Zstd {
+                level: p.int("level") as i32,
+                split: p.flag("split"),
+                dictionary: None,
+            }))
+        },
+    },
+    CodecEntry {
+        name: "zstd_dict",
+        help: "zstd with a dictionary trained by the train command",
+        params: &[
+            Param {
+                name: "dict",
+                kind: ParamKind::File,
+                default: "",
+                help: "dictionary file",
+            },
+            Param {
+                name: "level",
+                kind: ParamKind::Int {
+                    min: -(1 << 17),
+                    max: 22,
+                },
+                default: "3",
+                help: "compression level",
+            },
+            Param {
+                name: "split",
+                kind: BOOL,
+                default: "false",
+                help: "byte stream split before compressing, as the dictionary was trained",
+            },
+        ],
+        sweep: "",
+        build: |p| {
+            let level = p.int("level") as i32;
+            let (path, contents) = p.file("dict");
+            let dictionary = Dictionary::new(path, contents, level)?;
+            Ok(Box::new(Zstd {
+                level,
+                split: p.flag("split"),
+                dictionary: Some(dictionary),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "tsz",
+        help: "Gorilla as implemented by the tsz crate, real timestamps for CSV inputs",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Tsz)),
+    },
+    CodecEntry {
+        name: "snap",
+        help: "snappy frames over the little endian bytes",
+        params: &[Param {
+            name: "split",
+            kind: BOOL,
+            default: "false",
+            help: "byte stream split before compressing",
+        }],
+        sweep: "split=false/true",
+        build: |p| {
+            Ok(Box::new(Snap {
+                split: p.flag("split"),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "lz4",
+        help: "LZ4 block format",
+        params: &[
+            Param {
+                name: "acceleration",
+                kind: ParamKind::Int { min: 1, max: 65537 },
+                default: "1",
+                help: "higher is faster but compresses less",
+            },
+            Param {
+                name: "bitshuffle",
+                kind: BOOL,
+                default: "false",
+                help: "bit shuffle with blosc before compressing",
+            },
+            Param {
+                name: "split",
+                kind: BOOL,
+                default: "false",
+                help: "byte stream split before compressing",
+            },
+        ],
+        sweep: "split=false/true",
+        build: |p| {
+            Ok(Box::new(Lz4 {
+                mode: Lz4Mode::Block(p.int("acceleration") as i32),
+                bitshuffle: p.flag("bitshuffle"),
+                split: p.flag("split"),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "lz4_hc",
+        help: "LZ4_HC block format",
+        params: &[
+            Param {
+                name: "level",
+                kind: ParamKind::Int { min: 1, max: 12 },
+                default: "9",
+                help: "compression level",
+            },
+            Param {
+                name: "bitshuffle",
+                kind: BOOL,
+                default: "false",
+                help: "bit shuffle with blosc before compressing",
+            },
+            Param {
+                name: "split",
+                kind: BOOL,
+                default: "false",
+                help: "byte stream split before compressing",
+            },
+        ],
+        sweep: "split=false/true",
+        build: |p| {
+            Ok(Box::new(Lz4 {
+                mode: Lz4Mode::Hc(p.int("level") as i32),
+                bitshuffle: p.flag("bitshuffle"),
+                split: p.flag("split"),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "lz4f",
+        help: "LZ4 frame format",
+        params: &[
+            Param {
+                name: "bitshuffle",
+                kind: BOOL,
+                default: "false",
+                help: "bit shuffle with blosc before compressing",
+            },
+            Param {
+                name: "split",
+                kind: BOOL,
+                default: "false",
+                help: "byte stream split before compressing",
+            },
+        ],
+        sweep: "split=false/true",
+        build: |p| {
+            Ok(Box::new(Lz4 {
+                mode: Lz4Mode::Frame,
+                bitshuffle: p.flag("bitshuffle"),
+                split: p.flag("split"),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "gorilla",
+        help: "in-house Gorilla XOR encoder",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Gorilla)),
+    },
+    CodecEntry {
+        name: "gorilla_ts",
+        help: "in-house Gorilla with delta-of-delta timestamps, real ones for CSV inputs",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(GorillaTs)),
+    },
+    CodecEntry {
+        name: "chimp",
+        help: "Chimp, XOR with the previous value",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Chimp { log2_previous: 0 })),
+    },
+    CodecEntry {
+        name: "chimp128",
+        help: "Chimp128, XOR with the best of the previous 128 values",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Chimp { log2_previous: 7 })),
+    },
+    CodecEntry {
+        name: "patas",
+        help: "Patas, byte-aligned Chimp128 as in DuckDB",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Patas)),
+    },
+    CodecEntry {
+        name: "elf",
+        help: "Elf, Chimp after erasing mantissa bits that only encode decimal noise",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Elf)),
+    },
+    CodecEntry {
+        name: "zfp",
+        help: "zfp in reversible (lossless) mode",
+        params: &[],
+        sweep: "",
+        build: |_| {
+            Ok(Box::new(Zfp {
+                mode: ZfpMode::Reversible,
+            }))
+        },
+    },
+    CodecEntry {
+        name: "zfp_rate",
+        help: "zfp in fixed-rate mode, lossy",
+        params: &[Param {
+            name: "rate",
+            kind: ParamKind::Int { min: 1, max: 64 },
+            default: "16",
+            help: "bits per value",
+        }],
+        sweep: "rate=8/16/24/32/48",
+        build: |p| {
+            Ok(Box::new(Zfp {
+                mode: ZfpMode::FixedRate(p.int("rate") as f64),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "zfp_precision",
+        help: "zfp in fixed-precision mode, lossy",
+        params: &[Param {
+            name: "precision",
+            kind: ParamKind::Int { min: 1, max: 64 },
+            default: "32",
+            help: "bit planes kept per block",
+        }],
+        sweep: "precision=16/24/32/40/48",
+        build: |p| {
+            Ok(Box::new(Zfp {
+                mode: ZfpMode::FixedPrecision(p.int("precision") as u32),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "zfp_accuracy",
+        help: "zfp in fixed-accuracy mode, lossy",
+        params: &[Param {
+            name: "digits",
+            kind: ParamKind::Int { min: 0, max: 15 },
+            default: "3",
+            help: "absolute error tolerance of 10^-digits",
+        }],
+        sweep: "digits=1..6",
+        build: |p| {
+            Ok(Box::new(Zfp {
+                mode: ZfpMode::FixedAccuracy(10_f64.powi(-p.int("digits") as i32)),
+            }))
+        },
+    },
+    CodecEntry {
+        name: "fpzip",
+        help: "fpzip, lossless at full precision, lossy as f32 or with fewer bits",
+        params: &[
+            Param {
+                name: "type",
+                kind: ParamKind::Choice(&["f64", "f32"]),
+                default: "f64",
+                help: "float type compressed, f32 rounds every value",
+            },
+            Param {
+                name: "precision",
+                kind: ParamKind::Int { min: 0, max: 64 },
+                default: "0",
+                help: "bits kept per value, 0 for all, at most 32 for f32",
+            },
+        ],
+        sweep: "type=f64/f32,precision=0/16/24/32",
+        build: |p| {
+            let single = p.choice("type") == "f32";
+            let precision = p.int("precision") as u32;
+            if single && precision > 32 {
+                return Err(invalid(format!(
+                    "precision: {} is more than the 32 bits of an f32",
+                    precision
+                )));
+            }
+            Ok(Box::new(Fpzip { single, precision }))
+        },
+    },
+    CodecEntry {
+        name: "alp",
+        help: "ALP, decimals scaled to integers with a per-page exponent, exceptions kept apart",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(Alp)),
+    },
+    CodecEntry {
+        name: "decimal",
+        help: "rust_decimal integers at the page's largest scale, delta and zig-zag encoded",
+        params: &[],
+        sweep: "",
+        build: |_| Ok(Box::new(DecimalScaled)),
+    },
+    CodecEntry {
+        name: "fpc",
+        help: "FPC, XOR with the closer of an FCM and a DFCM hash table prediction",
+        params: &[Param {
+            name: "level",
+            kind: ParamKind::Int {
+                min: 1,
+                max: crate::fpc::MAX_LEVEL as i64,
+            },
+            default: "16",
+            help: "log2 of the entries in each prediction table",
+        }],
+        sweep: "level=8..20",
+        build: |p| {
+            Ok(Box::new(Fpc {
+                level: p.int("level") as u32,
+            }))
+        },
+    },
+];
+
+pub fn find(name: &str) -> io::Result<&'static CodecEntry> {
+    ENTRIES.iter().find(|e| e.name == name).ok_or_else(|| {
+        let names: Vec<&str> = ENTRIES.iter().map(|e| e.name).collect();
+        invalid(format!(
+            "unknown codec {}, expected one of: {}",
+            name,
+            names.join(", ")
+        ))
+    })
+}
+
+// expands a single value, range or list of alternatives and validates each one
+fn expand_value(param: &Param, value: &str) -> io::Result<Vec<String>> {
+    let values: Vec<String> = match param.kind {
+        ParamKind::Int { .. } if value.contains("..") => {
+            let (start, end) = value.split_once("..").unwrap();
+            let end = end.strip_prefix('=').unwrap_or(end);
+            let parse = |s: &str| {
+                s.trim()
+                    .parse::<i64>()
+                    .map_err(|_| invalid(format!("{}: {:?} is not an integer", param.name, s)))
+            };
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(invalid(format!("{}: empty range {}", param.name, value)));
+            }
+            (start..=end).map(|v| v.to_string()).collect()
+        }
+        ParamKind::File => vec![value.trim().to_string()],
+        _ => value.split('/').map(|v| v.trim().to_string()).collect(),
+    };
+
+    for value in &values {
+        match param.kind {
+            ParamKind::Int { min, max } => {
+                let v: i64 = value.parse().map_err(|_| {
+                    invalid(format!("{}: {:?} is not an integer", param.name, value))
+                })?;
+                if v < min || v > max {
+                    return Err(invalid(format!(
+                        "{}: {} is outside {}..={}",
+                        param.name, v, min, max
+                    )));
+                }
+            }
+            ParamKind::Choice(choices) => {
+                if !choices.contains(&value.as_str()) {
+                    return Err(invalid(format!(
+                        "{}: {:?} is not one of {}",
+                        param.name,
+                        value,
+                        choices.join(", ")
+                    )));
+                }
+            }
+            ParamKind::File => {
+                if value.is_empty() {
+                    return Err(invalid(format!("{}: no file given", param.name)));
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Parses a spec into every configuration it describes.
+///
+/// With `sweep` set, a spec naming just the codec expands to the codec's
+/// default sweep rather than to its defaults. The codec at the end of a
+/// pipeline always uses its defaults.
+pub fn parse(spec: &str, sweep: bool) -> io::Result<Vec<Config>> {
+    parse_with_files(spec, sweep, &Files::new())
+}
+
+// like `parse`, taking the files the spec names from `stored` when they are
+// there and reading the rest
+fn parse_with_files(spec: &str, sweep: bool, stored: &Files) -> io::Result<Vec<Config>> {
+    let (transforms, spec) = match spec.rsplit_once('|') {
+        Some((chain, spec)) => (pipeline::parse(chain)?, spec),
+        None => (Vec::new(), spec),
+    };
+    let sweep = sweep && transforms.is_empty();
+    let spec = spec.trim();
+    let (name, args) = match spec.split_once(':') {
+        Some((name, args)) => (name.trim(), args),
+        None => (spec, ""),
+    };
+    let entry = find(name)?;
+    let args = if args.trim().is_empty() && sweep {
+        entry.sweep
+    } else {
+        args
+    };
+
+    // assign every argument to a parameter
+    let mut given: Vec<Option<&str>> = vec![None; entry.params.len()];
+    let mut next_positional = 0;
+    for arg in args.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+        match arg.split_once('=') {
+            Some((key, value)) => {
+                let key = key.trim();
+                let index = entry
+                    .params
+                    .iter()
+                    .position(|p| p.name == key)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = entry.params.iter().map(|p| p.name).collect();
+                        invalid(format!(
+                            "{} has no parameter {}, expected one of: {}",
+                            entry.name,
+                            key,
+                            names.join(", ")
+                        ))
+                    })?;
+                if given[index].is_some() {
+                    return Err(invalid(format!("{} is given twice", key)));
+                }
+                given[index] = Some(value.trim());
+            }
+            None => {
+                while next_positional < given.len() && given[next_positional].is_some() {
+                    next_positional += 1;
+                }
+                if next_positional == given.len() {
+                    return Err(invalid(format!(
+                        "too many parameters for {}: {}",
+                        entry.name, arg
+                    )));
+                }
+                given[next_positional] = Some(arg);
+            }
+        }
+    }
+
+    // cartesian product of the values of every parameter
+    let mut combinations: Vec<Vec<(&'static str, String)>> = vec![Vec::new()];
+    for (param, value) in entry.params.iter().zip(given) {
+        let values = expand_value(param, value.unwrap_or(param.default))?;
+        combinations = combinations
+            .into_iter()
+            .flat_map(|prefix| {
+                values.iter().map(move |v| {
+                    let mut params = prefix.clone();
+                    params.push((param.name, v.clone()));
+                    params
+                })
+            })
+            .collect();
+    }
+
+    // read every file once, however many configurations name it
+    let mut files = Files::new();
+    for (i, param) in entry.params.iter().enumerate() {
+        if !matches!(param.kind, ParamKind::File) {
+            continue;
+        }
+        for values in &combinations {
+            let path = &values[i].1;
+            if files.iter().any(|(p, _)| p == path) {
+                continue;
+            }
+            let contents = match stored.iter().find(|(p, _)| p == path) {
+                Some((_, contents)) => contents.clone(),
+                None => fs::read(path)
+                    .map_err(|e| {
+                        io::Error::new(e.kind(), format!("{}: reading {}: {}", param.name, path, e))
+                    })?
+                    .into(),
+            };
+            files.push((path.clone(), contents));
+        }
+    }
+
+    let configs: Vec<Config> = combinations
+        .into_iter()
+        .map(|values| Config {
+            entry,
+            params: Params {
+                values,
+                files: files.clone(),
+            },
+            transforms: transforms.clone(),
+        })
+        .collect();
+    // building every configuration reports invalid combinations of parameters
+    // before a sweep starts rather than in the middle of it
+    for config in &configs {
+        let codec = (entry.build)(&config.params)?;
+        // undoing the transforms needs the exact values back
+        if !transforms.is_empty() && codec.lossy() {
+            return Err(invalid(format!(
+                "{} is lossy and cannot end a pipeline",
+                entry.name
+            )));
+        }
+    }
+    Ok(configs)
+}
+
+/// Parses a spec that must describe exactly one configuration.
+pub fn parse_one(spec: &str) -> io::Result<Config> {
+    parse_stored(spec, &Files::new())
+}
+
+/// Parses the spec of a compressed file, taking the files it names from those
+/// stored in the file rather than from where they were when it was written.
+pub fn parse_stored(spec: &str, stored: &Files) -> io::Result<Config> {
+    let mut configs = parse_with_files(spec, false, stored)?;
+    if configs.len() != 1 {
+        return Err(invalid(format!(
+            "{} describes {} configurations, expected one",
+            spec,
+            configs.len()
+        )));
+    }
+    Ok(configs.remove(0))
+}
+
+/// Prints every codec with its parameters, as shown by the `list` command.
+pub fn describe<W: io::Write>(w: &mut W) -> io::Result<()> {
+    for entry in ENTRIES {
+        writeln!(w, "{} - {}", entry.name, entry.help)?;
+        for param in entry.params {
+            let values = match param.kind {
+                ParamKind::Int { min, max } => format!("{}..={}", min, max),
+                ParamKind::Choice(choices) => choices.join("/"),
+                ParamKind::File => "<file>".to_string(),
+            };
+            // file parameters have no default
+            let default = match param.default {
+                "" => "required".to_string(),
+                default => format!("default {}", default),
+            };
+            writeln!(
+                w,
+                "    {}={} ({}): {}",
+                param.name, values, default, param.help
+            )?;
+        }
+        if !entry.sweep.is_empty() {
+            writeln!(w, "    bench sweeps {}", entry.sweep)?;
+        }
+    }
+    writeln!(
+        w,
+        "transforms, put before a lossless codec with |: {}",
+        pipeline::names().join(", ")
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(configs: &[Config]) -> Vec<String> {
+        configs.iter().map(Config::to_string).collect()
+    }
+
+    fn rejects(spec: &str, message: &str) {
+        let err = match parse(spec, false) {
+            Ok(configs) => panic!("{} parsed into {:?}", spec, specs(&configs)),
+            Err(err) => err,
+        };
+        assert!(
+            err.to_string().contains(message),
+            "{}: {:?} does not mention {:?}",
+            spec,
+            err.to_string(),
+            message
+        );
+    }
+
+    #[test]
+    fn fills_in_defaults_and_positional_parameters() {
+        assert_eq!(
+            parse_one("zstd").unwrap().to_string(),
+            "zstd:level=3,split=false"
+        );
+        assert_eq!(
+            parse_one("zstd:5").unwrap().to_string(),
+            "zstd:level=5,split=false"
+        );
+        let config = parse_one("blosc:lz4,shuffle=bit").unwrap();
+        assert_eq!(config.params.choice("compressor"), "lz4");
+        assert_eq!(config.params.choice("shuffle"), "bit");
+    }
+
+    #[test]
+    fn expands_ranges_and_alternatives() {
+        assert_eq!(
+            specs(&parse("zstd:level=1..3,split=false/true", false).unwrap()),
+            [
+                "zstd:level=1,split=false",
+                "zstd:level=1,split=true",
+                "zstd:level=2,split=false",
+                "zstd:level=2,split=true",
+                "zstd:level=3,split=false",
+                "zstd:level=3,split=true",
+            ]
+        );
+        assert_eq!(parse("zstd:level=-2..=-1", false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sweeps_a_bare_codec_name_only_when_asked() {
+        assert_eq!(parse("blosc", false).unwrap().len(), 1);
+        // 6 backends, 3 shuffles, 10 levels, 4 type sizes and 3 thread counts
+        assert_eq!(parse("blosc", true).unwrap().len(), 2160);
+        // the codec of a pipeline keeps its defaults
+        assert_eq!(parse("xor|zstd", true).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parses_the_defaults_and_sweep_of_every_codec() {
+        for entry in ENTRIES {
+            if entry
+                .params
+                .iter()
+                .any(|p| matches!(p.kind, ParamKind::File))
+            {
+                continue;
+            }
+            assert_eq!(parse(entry.name, false).unwrap().len(), 1, "{}", entry.name);
+            assert!(
+                !parse(entry.name, true).unwrap().is_empty(),
+                "{}",
+                entry.name
+            );
+        }
+    }
+
+    #[test]
+    fn prints_specs_that_parse_back_to_the_same_config() {
+        for spec in [
+            "zstd:level=-5,split=true",
+            "blosc:zlib,shuffle=byte,clevel=9",
+            "delta|zigzag|lz4",
+            "fpzip:f32,precision=24",
+        ] {
+            let config = parse_one(spec).unwrap();
+            let reparsed = parse_one(&config.to_string()).unwrap();
+            assert_eq!(reparsed.to_string(), config.to_string());
+            assert_eq!(reparsed.params, config.params);
+            assert_eq!(reparsed.transforms, config.transforms);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        rejects("brotli", "unknown codec brotli");
+        rejects("zstd:speed=3", "zstd has no parameter speed");
+        rejects("zstd:level=3,level=4", "level is given twice");
+        rejects("zstd:3,true,4", "too many parameters");
+        rejects("zstd:level=23", "outside");
+        rejects("zstd:level=high", "not an integer");
+        rejects("zstd:level=5..1", "empty range");
+        rejects("blosc:brotli", "not one of");
+        rejects("rotate|zstd", "unknown transform rotate");
+        rejects("xor|zfp_rate", "lossy and cannot end a pipeline");
+        rejects("fpzip:f32,precision=48", "32");
+        rejects("zstd_dict", "no file given");
+    }
+
+    #[test]
+    fn rejects_sweeps_where_one_config_is_expected() {
+        let err = parse_one("zstd:level=1/2").err().unwrap();
+        assert!(err.to_string().contains("2 configurations"));
+    }
+
+    #[test]
+    fn reads_files_when_parsing_unless_they_are_stored() {
+        let err = parse("zstd_dict:dict=/nonexistent/open.dict", false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let values: Vec<f64> = (0..8192).map(|i| (i % 997) as f64 * 0.25).collect();
+        let dictionary = crate::dictionary::train(&values, 256, false, 4096).unwrap();
+        let stored: Files = vec![("/nonexistent/open.dict".to_string(), dictionary.into())];
+        let config = parse_stored("zstd_dict:/nonexistent/open.dict", &stored).unwrap();
+        assert_eq!(config.params.files(), &stored);
+        assert!(config.build().is_ok());
+    }
+}
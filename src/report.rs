@@ -0,0 +1,426 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bench::{BenchResult, Phase, Timing};
+use crate::stats::Summary;
+use crate::verify::ErrorStats;
+
+/// The machine a benchmark ran on, recorded with every result.
+#[derive(Clone, Debug, Serialize)]
+pub struct Machine {
+    pub hostname: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub cpu: String,
+    pub cpus: usize,
+}
+
+impl Machine {
+    pub fn detect() -> Machine {
+        let hostname = fs::read_to_string("/etc/hostname")
+            .map(|h| h.trim().to_string())
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_default();
+        // only available on linux, left empty elsewhere
+        let cpu = fs::read_to_string("/proc/cpuinfo")
+            .ok()
+            .and_then(|info| {
+                info.lines()
+                    .find(|line| line.starts_with("model name"))
+                    .and_then(|line| line.split(':').nth(1))
+                    .map(|model| model.trim().to_string())
+            })
+            .unwrap_or_default();
+        Machine {
+            hostname,
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            cpu,
+            cpus: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PageRecord {
+    num_values: usize,
+    compressed_bytes: usize,
+    compression_ratio: f64,
+    /// Only for lossy codecs.
+    errors: Option<ErrorRecord>,
+    /// Median over the repetitions, per phase.
+    ns: BTreeMap<&'static str, u128>,
+    /// Every repetition, per phase.
+    ns_reps: BTreeMap<&'static str, Vec<u128>>,
+}
+
+#[derive(Serialize)]
+struct TimingRecord {
+    /// Seconds to process every page once, summarized over the repetitions.
+    total_secs: Summary,
+    mb_per_sec: f64,
+    values_per_sec: f64,
+}
+
+impl From<Timing> for TimingRecord {
+    fn from(timing: Timing) -> Self {
+        TimingRecord {
+            total_secs: timing.total_secs,
+            mb_per_sec: timing.mb_per_sec,
+            values_per_sec: timing.values_per_sec,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorRecord {
+    tolerance: f64,
+    max_abs_error: f64,
+    max_rel_error: f64,
+    rmse: f64,
+    /// In dB, null for an exact round trip.
+    psnr: f64,
+    /// Fraction of values within the tolerance.
+    within_tolerance: f64,
+}
+
+impl From<ErrorStats> for ErrorRecord {
+    fn from(errors: ErrorStats) -> Self {
+        ErrorRecord {
+            tolerance: errors.tolerance,
+            max_abs_error: errors.max_abs_error,
+            max_rel_error: errors.max_rel_error,
+            rmse: errors.rmse(),
+            psnr: errors.psnr(),
+            within_tolerance: errors.within_tolerance_fraction(),
+        }
+    }
+}
+
+/// One line of the JSON Lines output.
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: u64,
+    dataset: &'a str,
+    codec: &'a str,
+    params: BTreeMap<&'static str, &'a str>,
+    chunking: String,
+    /// Number of values per page.
+    page_sizes: Summary,
+    num_values: usize,
+    uncompressed_bytes: usize,
+    compressed_bytes: usize,
+    compression_ratio: f64,
+    bits_per_value: f64,
+    page_ratios: Summary,
+    /// Only for lossy codecs.
+    errors: Option<ErrorRecord>,
+    warmup: usize,
+    repetitions: usize,
+    /// Keyed by phase: prepare, encode, decode and finalize.
+    timing: BTreeMap<&'static str, TimingRecord>,
+    pages: Vec<PageRecord>,
+    machine: &'a Machine,
+}
+
+const CSV_HEADER: &str = "timestamp,dataset,codec,params,chunking,page,num_values,\
+    uncompressed_bytes,compressed_bytes,compression_ratio,repetitions,prepare_ns,encode_ns,\
+    decode_ns,finalize_ns,max_abs_error,max_rel_error,rmse,psnr,within_tolerance,\
+    hostname,os,arch,cpus";
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// NaN for empty datasets, which Duration cannot represent
+fn duration(secs: f64) -> Duration {
+    if secs.is_finite() && secs >= 0.0 {
+        Duration::from_secs_f64(secs)
+    } else {
+        Duration::ZERO
+    }
+}
+
+fn params_string(result: &BenchResult) -> String {
+    result
+        .params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The results files for one codec on one dataset: the text report
+/// (`zstd_high.txt`), JSON Lines (`zstd_high.jsonl`) and CSV (`zstd_high.csv`).
+pub struct Reports {
+    dataset: String,
+    machine: Machine,
+    text: BufWriter<File>,
+    jsonl: BufWriter<File>,
+    csv: BufWriter<File>,
+}
+
+impl Reports {
+    pub fn create(
+        out_dir: &Path,
+        codec: &str,
+        dataset: &str,
+        stem: &str,
+        machine: Machine,
+    ) -> io::Result<Reports> {
+        let create = |name: String| -> io::Result<BufWriter<File>> {
+            Ok(BufWriter::new(File::create(out_dir.join(name))?))
+        };
+
+        let mut csv = create(format!("{}_{}.csv", codec, stem))?;
+        writeln!(csv, "{}", CSV_HEADER)?;
+        Ok(Reports {
+            dataset: dataset.to_string(),
+            machine,
+            text: create(format!("{}_{}.txt", codec, stem))?,
+            jsonl: create(format!("{}_{}.jsonl", codec, stem))?,
+            csv,
+        })
+    }
+
+    pub fn write(&mut self, result: &BenchResult) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.write_text(result)?;
+        self.write_jsonl(result, timestamp)?;
+        self.write_csv(result, timestamp)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.text.flush()?;
+        self.jsonl.flush()?;
+        self.csv.flush()
+    }
+
+    fn write_text(&mut self, result: &BenchResult) -> io::Result<()> {
+        let chunks = result.pages.len();
+
+        let mut results = String::new();
+        for (name, value) in &result.params {
+            results.push_str(&format!("{}: {}\n", name, value));
+        }
+        let page_sizes = result.page_sizes();
+        let page_ratios = result.page_ratios();
+        results.push_str(&format!(
+            "Chunking: {} ({} pages, min {}, median {}, max {} values per page)\n\
+            Compression ratio: {}\n\
+            Bits per value: {}\n\
+            Page compression ratio: min {}, median {}, p95 {}, max {}, mean {}\n\
+            Repetitions: {} (after {} warmup)\n",
+            result.chunking,
+            chunks,
+            page_sizes.min,
+            page_sizes.median,
+            page_sizes.max,
+            result.compression_ratio(),
+            result.bits_per_value(),
+            page_ratios.min,
+            page_ratios.median,
+            page_ratios.p95,
+            page_ratios.max,
+            page_ratios.mean,
+            result.options.repetitions,
+            result.options.warmup,
+        ));
+        if let Some(errors) = result.errors() {
+            results.push_str(&format!(
+                "Max absolute error: {}\n\
+                Max relative error: {}\n\
+                RMSE: {}\n\
+                PSNR: {} dB\n\
+                Within tolerance {}: {:.4}%\n",
+                errors.max_abs_error,
+                errors.max_rel_error,
+                errors.rmse(),
+                errors.psnr(),
+                errors.tolerance,
+                errors.within_tolerance_fraction() * 100.0,
+            ));
+        }
+        for phase in Phase::ALL {
+            let timing = result.timing(phase);
+            let total = &timing.total_secs;
+            results.push_str(&format!(
+                "Total {phase} time: median {:?}, mean {:?} +/- {:?} (95% CI), stddev {:?}\n\
+                Average {phase} time per page: {:?}\n\
+                Throughput of {phase}: {:.2} MB/s, {:.0} values/s\n",
+                duration(total.median),
+                duration(total.mean),
+                duration(total.ci95),
+                duration(total.stddev),
+                duration(total.median / chunks as f64),
+                timing.mb_per_sec,
+                timing.values_per_sec,
+                phase = phase.name(),
+            ));
+        }
+        results.push('\n');
+        write!(self.text, "{}", results)
+    }
+
+    fn write_jsonl(&mut self, result: &BenchResult, timestamp: u64) -> io::Result<()> {
+        let pages: Vec<PageRecord> = result
+            .pages
+            .iter()
+            .map(|p| PageRecord {
+                num_values: p.num_values,
+                compressed_bytes: p.compressed_bytes,
+                compression_ratio: p.compression_ratio(),
+                errors: p.errors.map(ErrorRecord::from),
+                ns: Phase::ALL
+                    .iter()
+                    .map(|&phase| (phase.name(), p.median_time(phase).as_nanos()))
+                    .collect(),
+                ns_reps: Phase::ALL
+                    .iter()
+                    .map(|&phase| {
+                        let reps = p.times(phase).iter().map(Duration::as_nanos).collect();
+                        (phase.name(), reps)
+                    })
+                    .collect(),
+            })
+            .collect();
+        let record = Record {
+            timestamp,
+            dataset: &self.dataset,
+            codec: &result.codec,
+            params: result
+                .params
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect(),
+            chunking: result.chunking.to_string(),
+            page_sizes: result.page_sizes(),
+            num_values: result.num_values(),
+            uncompressed_bytes: result.uncompressed_bytes(),
+            compressed_bytes: result.compressed_bytes(),
+            compression_ratio: result.compression_ratio(),
+            bits_per_value: result.bits_per_value(),
+            page_ratios: result.page_ratios(),
+            errors: result.errors().map(ErrorRecord::from),
+            warmup: result.options.warmup,
+            repetitions: result.options.repetitions,
+            timing: Phase::ALL
+                .iter()
+                .map(|&phase| (phase.name(), result.timing(phase).into()))
+                .collect(),
+            pages,
+            machine: &self.machine,
+        };
+        serde_json::to_writer(&mut self.jsonl, &record)?;
+        writeln!(self.jsonl)
+    }
+
+    // one row per page
+    fn write_csv(&mut self, result: &BenchResult, timestamp: u64) -> io::Result<()> {
+        let params = csv_field(&params_string(result));
+        for (i, page) in result.pages.iter().enumerate() {
+            // left empty for lossless codecs
+            let errors = match page.errors {
+                Some(e) => format!(
+                    "{},{},{},{},{}",
+                    e.max_abs_error,
+                    e.max_rel_error,
+                    e.rmse(),
+                    e.psnr(),
+                    e.within_tolerance_fraction()
+                ),
+                None => ",,,,".to_string(),
+            };
+            writeln!(
+                self.csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                timestamp,
+                csv_field(&self.dataset),
+                csv_field(&result.codec),
+                params,
+                csv_field(&result.chunking.to_string()),
+                i,
+                page.num_values,
+                page.uncompressed_bytes(),
+                page.compressed_bytes,
+                page.compression_ratio(),
+                result.options.repetitions,
+                page.median_time(Phase::Prepare).as_nanos(),
+                page.median_time(Phase::Encode).as_nanos(),
+                page.median_time(Phase::Decode).as_nanos(),
+                page.median_time(Phase::Finalize).as_nanos(),
+                errors,
+                csv_field(&self.machine.hostname),
+                self.machine.os,
+                self.machine.arch,
+                self.machine.cpus,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+const SUMMARY_HEADER: &str = "dataset,codec,params,chunking,num_values,compressed_bytes,\
+    compression_ratio,bits_per_value,prepare_mb_s,encode_mb_s,decode_mb_s,finalize_mb_s,\
+    max_abs_error";
+
+/// One row per configuration for every codec benchmarked on a dataset
+/// (`summary_high.csv`), to compare them in a single table.
+pub struct SummaryTable {
+    dataset: String,
+    csv: BufWriter<File>,
+}
+
+impl SummaryTable {
+    pub fn create(out_dir: &Path, dataset: &str, stem: &str) -> io::Result<SummaryTable> {
+        let mut csv = BufWriter::new(File::create(out_dir.join(format!("summary_{}.csv", stem)))?);
+        writeln!(csv, "{}", SUMMARY_HEADER)?;
+        Ok(SummaryTable {
+            dataset: dataset.to_string(),
+            csv,
+        })
+    }
+
+    pub fn write(&mut self, result: &BenchResult) -> io::Result<()> {
+        let throughput: Vec<String> = Phase::ALL
+            .iter()
+            .map(|&phase| result.timing(phase).mb_per_sec.to_string())
+            .collect();
+        // left empty for lossless codecs
+        let max_abs_error = result
+            .errors()
+            .map(|e| e.max_abs_error.to_string())
+            .unwrap_or_default();
+        writeln!(
+            self.csv,
+            "{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&self.dataset),
+            csv_field(&result.codec),
+            csv_field(&params_string(result)),
+            csv_field(&result.chunking.to_string()),
+            result.num_values(),
+            result.compressed_bytes(),
+            result.compression_ratio(),
+            result.bits_per_value(),
+            throughput.join(","),
+            max_abs_error,
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.csv.flush()
+    }
+}
@@ -0,0 +1,572 @@
+//! Byte and bit shuffles, the filters blosc applies before compressing.
+//!
+//! Both treat the input as elements of `width` bytes. The byte shuffle writes
+//! byte k of every element, then byte k + 1 and so on. The bit shuffle goes one
+//! step further and writes bit b of byte k of every element as one row of bits,
+//! packed 8 elements to a byte with the first element in the lowest bit, like
+//! the bitshuffle library. It works on groups of 8 elements, so elements after
+//! the last full group are copied as they are, as are bytes after the last full
+//! element.
+//!
+//! The bit shuffle is a byte shuffle followed by an 8x8 bit transpose of every
+//! 8 bytes of each stream, and the other way around for the bit unshuffle. For
+//! elements of 4 and 8 bytes on x86_64 both steps have SSE2 and AVX2 paths,
+//! picked at run time, which handle blocks of 16 or 32 elements; the rest of
+//! the page and other widths go through the scalar code.
+
+use std::ops::Range;
+
+/// Byte shuffles `src` made of `width` byte elements.
+pub fn byteshuffle(src: &[u8], width: usize) -> Vec<u8> {
+    let count = src.len() / width;
+    let mut dst = src.to_vec();
+    let done = simd::byteshuffle(src, &mut dst, width, count);
+    byteshuffle_scalar(src, &mut dst, width, count, done..count);
+    dst
+}
+
+/// Inverse of `byteshuffle`.
+pub fn byteunshuffle(src: &[u8], width: usize) -> Vec<u8> {
+    let count = src.len() / width;
+    let mut dst = src.to_vec();
+    let done = simd::byteunshuffle(src, &mut dst, width, count);
+    byteunshuffle_scalar(src, &mut dst, width, count, done..count);
+    dst
+}
+
+/// Bit shuffles `src` made of `width` byte elements.
+pub fn bitshuffle(src: &[u8], width: usize) -> Vec<u8> {
+    let count = src.len() / width / 8 * 8;
+    let streams = byteshuffle(&src[..count * width], width);
+    let mut dst = src.to_vec();
+    let done = simd::bit_rows(&streams, &mut dst, width, count);
+    bit_rows_scalar(&streams, &mut dst, width, count, done..count);
+    dst
+}
+
+/// Inverse of `bitshuffle`.
+pub fn bitunshuffle(src: &[u8], width: usize) -> Vec<u8> {
+    let count = src.len() / width / 8 * 8;
+    let mut streams = vec![0_u8; count * width];
+    let done = simd::bit_columns(src, &mut streams, width, count);
+    bit_columns_scalar(src, &mut streams, width, count, done..count);
+    let mut dst = src.to_vec();
+    dst[..count * width].copy_from_slice(&byteunshuffle(&streams, width));
+    dst
+}
+
+fn byteshuffle_scalar(
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    count: usize,
+    elements: Range<usize>,
+) {
+    for i in elements {
+        for k in 0..width {
+            dst[k * count + i] = src[i * width + k];
+        }
+    }
+}
+
+fn byteunshuffle_scalar(
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    count: usize,
+    elements: Range<usize>,
+) {
+    for i in elements {
+        for k in 0..width {
+            dst[i * width + k] = src[k * count + i];
+        }
+    }
+}
+
+// turns the byte streams of `count` elements into bit rows, for the elements in
+// `elements`, which start and end at multiples of 8
+fn bit_rows_scalar(
+    streams: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    count: usize,
+    elements: Range<usize>,
+) {
+    let row_bytes = count / 8;
+    for k in 0..width {
+        let stream = &streams[k * count..(k + 1) * count];
+        for group in elements.start / 8..elements.end / 8 {
+            for bit in 0..8 {
+                let mut row = 0;
+                for (j, &byte) in stream[group * 8..group * 8 + 8].iter().enumerate() {
+                    row |= ((byte >> bit) & 1) << j;
+                }
+                dst[(k * 8 + bit) * row_bytes + group] = row;
+            }
+        }
+    }
+}
+
+// inverse of `bit_rows_scalar`
+fn bit_columns_scalar(
+    src: &[u8],
+    streams: &mut [u8],
+    width: usize,
+    count: usize,
+    elements: Range<usize>,
+) {
+    let row_bytes = count / 8;
+    for k in 0..width {
+        for group in elements.start / 8..elements.end / 8 {
+            for j in 0..8 {
+                let mut byte = 0;
+                for bit in 0..8 {
+                    let row = src[(k * 8 + bit) * row_bytes + group];
+                    byte |= ((row >> j) & 1) << bit;
+                }
+                streams[k * count + group * 8 + j] = byte;
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod simd {
+    // no fast paths, everything is left to the scalar code
+    pub fn byteshuffle(_: &[u8], _: &mut [u8], _: usize, _: usize) -> usize {
+        0
+    }
+
+    pub fn byteunshuffle(_: &[u8], _: &mut [u8], _: usize, _: usize) -> usize {
+        0
+    }
+
+    pub fn bit_rows(_: &[u8], _: &mut [u8], _: usize, _: usize) -> usize {
+        0
+    }
+
+    pub fn bit_columns(_: &[u8], _: &mut [u8], _: usize, _: usize) -> usize {
+        0
+    }
+}
+
+/// The fast paths. Each returns how many elements it handled, always a whole
+/// number of blocks from the start.
+///
+/// The byte shuffle of 16 elements transposes a matrix of 16 x `width` bytes
+/// held in `width` registers. Interleaving the bytes of register p and
+/// register p + width / 2 into registers 2p and 2p + 1 rotates the bits of
+/// every byte's address (register, then position) left by one. Four rounds
+/// move the element index from the high to the low bits, which is the
+/// shuffle; log2(width) rounds undo it. AVX2 does the same on two blocks of 16
+/// elements at once, one in each 128 bit lane.
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    fn supported(width: usize) -> bool {
+        width == 4 || width == 8
+    }
+
+    fn log2(width: usize) -> usize {
+        width.trailing_zeros() as usize
+    }
+
+    pub fn byteshuffle(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        if !supported(width) {
+            0
+        } else if is_x86_feature_detected!("avx2") {
+            unsafe { byteshuffle_avx2(src, dst, width, count) }
+        } else {
+            unsafe { byteshuffle_sse2(src, dst, width, count) }
+        }
+    }
+
+    pub fn byteunshuffle(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        if !supported(width) {
+            0
+        } else if is_x86_feature_detected!("avx2") {
+            unsafe { byteunshuffle_avx2(src, dst, width, count) }
+        } else {
+            unsafe { byteunshuffle_sse2(src, dst, width, count) }
+        }
+    }
+
+    pub fn bit_rows(streams: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        if !supported(width) {
+            0
+        } else if is_x86_feature_detected!("avx2") {
+            unsafe { bit_rows_avx2(streams, dst, width, count) }
+        } else {
+            unsafe { bit_rows_sse2(streams, dst, width, count) }
+        }
+    }
+
+    pub fn bit_columns(src: &[u8], streams: &mut [u8], width: usize, count: usize) -> usize {
+        if !supported(width) {
+            0
+        } else if is_x86_feature_detected!("avx2") {
+            unsafe { bit_columns_avx2(src, streams, width, count) }
+        } else {
+            unsafe { bit_columns_sse2(src, streams, width, count) }
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn interleave_sse2(regs: &mut [__m128i; 8], width: usize, rounds: usize) {
+        let half = width / 2;
+        for _ in 0..rounds {
+            let prev = *regs;
+            for p in 0..half {
+                regs[2 * p] = _mm_unpacklo_epi8(prev[p], prev[p + half]);
+                regs[2 * p + 1] = _mm_unpackhi_epi8(prev[p], prev[p + half]);
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn interleave_avx2(regs: &mut [__m256i; 8], width: usize, rounds: usize) {
+        let half = width / 2;
+        for _ in 0..rounds {
+            let prev = *regs;
+            for p in 0..half {
+                regs[2 * p] = _mm256_unpacklo_epi8(prev[p], prev[p + half]);
+                regs[2 * p + 1] = _mm256_unpackhi_epi8(prev[p], prev[p + half]);
+            }
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn byteshuffle_sse2(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let blocks = count / 16;
+        let mut regs = [_mm_setzero_si128(); 8];
+        for block in 0..blocks {
+            let first = block * 16;
+            for (r, reg) in regs[..width].iter_mut().enumerate() {
+                *reg = _mm_loadu_si128(src[first * width + 16 * r..].as_ptr() as *const __m128i);
+            }
+            interleave_sse2(&mut regs, width, 4);
+            for (k, reg) in regs[..width].iter().enumerate() {
+                let out = dst[k * count + first..k * count + first + 16].as_mut_ptr();
+                _mm_storeu_si128(out as *mut __m128i, *reg);
+            }
+        }
+        blocks * 16
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn byteunshuffle_sse2(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let blocks = count / 16;
+        let mut regs = [_mm_setzero_si128(); 8];
+        for block in 0..blocks {
+            let first = block * 16;
+            for (k, reg) in regs[..width].iter_mut().enumerate() {
+                let stream = src[k * count + first..k * count + first + 16].as_ptr();
+                *reg = _mm_loadu_si128(stream as *const __m128i);
+            }
+            interleave_sse2(&mut regs, width, log2(width));
+            for (r, reg) in regs[..width].iter().enumerate() {
+                let out = dst[first * width + 16 * r..first * width + 16 * r + 16].as_mut_ptr();
+                _mm_storeu_si128(out as *mut __m128i, *reg);
+            }
+        }
+        blocks * 16
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn byteshuffle_avx2(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let blocks = count / 32;
+        let mut regs = [_mm256_setzero_si256(); 8];
+        for block in 0..blocks {
+            let first = block * 32;
+            for (r, reg) in regs[..width].iter_mut().enumerate() {
+                let low = &src[first * width + 16 * r..];
+                let high = &src[(first + 16) * width + 16 * r..];
+                *reg = _mm256_inserti128_si256(
+                    _mm256_castsi128_si256(_mm_loadu_si128(low.as_ptr() as *const __m128i)),
+                    _mm_loadu_si128(high.as_ptr() as *const __m128i),
+                    1,
+                );
+            }
+            interleave_avx2(&mut regs, width, 4);
+            for (k, reg) in regs[..width].iter().enumerate() {
+                let out = dst[k * count + first..k * count + first + 32].as_mut_ptr();
+                _mm256_storeu_si256(out as *mut __m256i, *reg);
+            }
+        }
+        blocks * 32
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn byteunshuffle_avx2(src: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let blocks = count / 32;
+        let mut regs = [_mm256_setzero_si256(); 8];
+        for block in 0..blocks {
+            let first = block * 32;
+            for (k, reg) in regs[..width].iter_mut().enumerate() {
+                let stream = src[k * count + first..k * count + first + 32].as_ptr();
+                *reg = _mm256_loadu_si256(stream as *const __m256i);
+            }
+            interleave_avx2(&mut regs, width, log2(width));
+            for (r, reg) in regs[..width].iter().enumerate() {
+                let low = dst[first * width + 16 * r..first * width + 16 * r + 16].as_mut_ptr();
+                _mm_storeu_si128(low as *mut __m128i, _mm256_castsi256_si128(*reg));
+                let at = (first + 16) * width + 16 * r;
+                let high = dst[at..at + 16].as_mut_ptr();
+                _mm_storeu_si128(high as *mut __m128i, _mm256_extracti128_si256(*reg, 1));
+            }
+        }
+        blocks * 32
+    }
+
+    // movemask collects the top bit of every byte, so doubling the bytes
+    // between masks gives bit 7, then bit 6 and so on of every element
+    #[target_feature(enable = "sse2")]
+    unsafe fn bit_rows_sse2(streams: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let row_bytes = count / 8;
+        let chunks = count / 16;
+        for k in 0..width {
+            for chunk in 0..chunks {
+                let at = k * count + chunk * 16;
+                let mut v = _mm_loadu_si128(streams[at..at + 16].as_ptr() as *const __m128i);
+                for bit in (0..8).rev() {
+                    let mask = _mm_movemask_epi8(v) as u16;
+                    let row = (k * 8 + bit) * row_bytes + chunk * 2;
+                    dst[row..row + 2].copy_from_slice(&mask.to_le_bytes());
+                    v = _mm_add_epi8(v, v);
+                }
+            }
+        }
+        chunks * 16
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn bit_rows_avx2(streams: &[u8], dst: &mut [u8], width: usize, count: usize) -> usize {
+        let row_bytes = count / 8;
+        let chunks = count / 32;
+        for k in 0..width {
+            for chunk in 0..chunks {
+                let at = k * count + chunk * 32;
+                let mut v = _mm256_loadu_si256(streams[at..at + 32].as_ptr() as *const __m256i);
+                for bit in (0..8).rev() {
+                    let mask = _mm256_movemask_epi8(v) as u32;
+                    let row = (k * 8 + bit) * row_bytes + chunk * 4;
+                    dst[row..row + 4].copy_from_slice(&mask.to_le_bytes());
+                    v = _mm256_add_epi8(v, v);
+                }
+            }
+        }
+        chunks * 32
+    }
+
+    // gathers the 8 row bytes of each group of 8 elements next to each other,
+    // then takes bit 7, bit 6 and so on of them like `bit_rows_sse2`, which
+    // gives element 7, element 6 and so on of the group
+    #[target_feature(enable = "sse2")]
+    unsafe fn bit_columns_sse2(
+        src: &[u8],
+        streams: &mut [u8],
+        width: usize,
+        count: usize,
+    ) -> usize {
+        let row_bytes = count / 8;
+        let chunks = count / 16;
+        let mut gathered = [0_u8; 16];
+        for k in 0..width {
+            for chunk in 0..chunks {
+                for bit in 0..8 {
+                    let row = (k * 8 + bit) * row_bytes + chunk * 2;
+                    gathered[bit] = src[row];
+                    gathered[8 + bit] = src[row + 1];
+                }
+                let mut v = _mm_loadu_si128(gathered.as_ptr() as *const __m128i);
+                let out = k * count + chunk * 16;
+                for j in (0..8).rev() {
+                    let mask = _mm_movemask_epi8(v);
+                    streams[out + j] = mask as u8;
+                    streams[out + 8 + j] = (mask >> 8) as u8;
+                    v = _mm_add_epi8(v, v);
+                }
+            }
+        }
+        chunks * 16
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn bit_columns_avx2(
+        src: &[u8],
+        streams: &mut [u8],
+        width: usize,
+        count: usize,
+    ) -> usize {
+        let row_bytes = count / 8;
+        let chunks = count / 32;
+        let mut gathered = [0_u8; 32];
+        for k in 0..width {
+            for chunk in 0..chunks {
+                for bit in 0..8 {
+                    let row = (k * 8 + bit) * row_bytes + chunk * 4;
+                    for group in 0..4 {
+                        gathered[group * 8 + bit] = src[row + group];
+                    }
+                }
+                let mut v = _mm256_loadu_si256(gathered.as_ptr() as *const __m256i);
+                let out = k * count + chunk * 32;
+                for j in (0..8).rev() {
+                    let mask = _mm256_movemask_epi8(v) as u32;
+                    for group in 0..4 {
+                        streams[out + group * 8 + j] = (mask >> (group * 8)) as u8;
+                    }
+                    v = _mm256_add_epi8(v, v);
+                }
+            }
+        }
+        chunks * 32
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::shuffle::tests::random_bytes;
+        use crate::shuffle::{
+            bit_columns_scalar, bit_rows_scalar, byteshuffle_scalar, byteunshuffle_scalar,
+        };
+        use std::ops::Range;
+
+        type Path = unsafe fn(&[u8], &mut [u8], usize, usize) -> usize;
+        type Scalar = fn(&[u8], &mut [u8], usize, usize, Range<usize>);
+
+        // SSE2 is part of x86_64, AVX2 is only run where the CPU has it
+        fn paths(sse2: Path, avx2: Path) -> Vec<(&'static str, Path, usize)> {
+            let mut paths = vec![("sse2", sse2 as Path, 16)];
+            if is_x86_feature_detected!("avx2") {
+                paths.push(("avx2", avx2 as Path, 32));
+            }
+            paths
+        }
+
+        // the whole blocks of `path` followed by the scalar rest, as the public
+        // functions run them, against the scalar code alone
+        fn assert_matches_scalar(sse2: Path, avx2: Path, scalar: Scalar, counts: &[usize]) {
+            for (name, path, block) in paths(sse2, avx2) {
+                for width in [4, 8] {
+                    for &count in counts {
+                        let src = random_bytes(count * width);
+                        let mut expected = vec![0; count * width];
+                        scalar(&src, &mut expected, width, count, 0..count);
+
+                        let mut actual = vec![0; count * width];
+                        let done = unsafe { path(&src, &mut actual, width, count) };
+                        assert_eq!(done, count / block * block, "{} of {}", name, count);
+                        scalar(&src, &mut actual, width, count, done..count);
+                        assert_eq!(
+                            expected, actual,
+                            "{} on {} elements of {} bytes",
+                            name, count, width
+                        );
+                    }
+                }
+            }
+        }
+
+        // around the blocks of 16 and 32 elements, and a page that is not a
+        // multiple of either
+        const BYTE_COUNTS: [usize; 13] = [0, 1, 15, 16, 17, 31, 32, 33, 47, 63, 64, 65, 1000];
+        // the bit shuffle only works on groups of 8 elements
+        const BIT_COUNTS: [usize; 10] = [0, 8, 16, 24, 32, 40, 56, 64, 72, 1000];
+
+        #[test]
+        fn byteshuffle_paths_match_scalar() {
+            assert_matches_scalar(
+                byteshuffle_sse2,
+                byteshuffle_avx2,
+                byteshuffle_scalar,
+                &BYTE_COUNTS,
+            );
+        }
+
+        #[test]
+        fn byteunshuffle_paths_match_scalar() {
+            assert_matches_scalar(
+                byteunshuffle_sse2,
+                byteunshuffle_avx2,
+                byteunshuffle_scalar,
+                &BYTE_COUNTS,
+            );
+        }
+
+        #[test]
+        fn bit_rows_paths_match_scalar() {
+            assert_matches_scalar(bit_rows_sse2, bit_rows_avx2, bit_rows_scalar, &BIT_COUNTS);
+        }
+
+        #[test]
+        fn bit_columns_paths_match_scalar() {
+            assert_matches_scalar(
+                bit_columns_sse2,
+                bit_columns_avx2,
+                bit_columns_scalar,
+                &BIT_COUNTS,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift bytes from a fixed seed, so that failures repeat
+    pub(super) fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_every_width_and_length() {
+        for width in [1, 2, 3, 4, 8, 16] {
+            for len in [0, 1, 15, 31, 33, 64, 100, 257, 1029] {
+                let src = random_bytes(len);
+                let shuffled = byteshuffle(&src, width);
+                assert_eq!(byteunshuffle(&shuffled, width), src, "{} of {}", len, width);
+                let shuffled = bitshuffle(&src, width);
+                assert_eq!(bitunshuffle(&shuffled, width), src, "{} of {}", len, width);
+            }
+        }
+    }
+
+    #[test]
+    fn byteshuffle_writes_each_byte_of_every_element_in_turn() {
+        let src = [0, 1, 2, 3, 10, 11, 12, 13, 20, 21, 22, 23];
+        assert_eq!(
+            byteshuffle(&src, 4),
+            [0, 10, 20, 1, 11, 21, 2, 12, 22, 3, 13, 23]
+        );
+    }
+
+    #[test]
+    fn bitshuffle_writes_the_first_element_in_the_lowest_bit() {
+        // bit b of element j becomes bit j of row b
+        let src = [0xff, 0, 0, 0, 0, 0, 0, 0x01];
+        assert_eq!(
+            bitshuffle(&src, 1),
+            [0x81, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn copies_what_is_left_after_the_last_element_or_group() {
+        // 9 elements of 4 bytes and 3 more bytes
+        let src = random_bytes(39);
+        assert_eq!(byteshuffle(&src, 4)[36..], src[36..]);
+        // the ninth element is not in a group of 8
+        assert_eq!(bitshuffle(&src, 4)[32..], src[32..]);
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+
+/// Distribution of a set of measurements, e.g. the compression ratios of every page.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    /// Sample standard deviation, 0 for fewer than two values.
+    pub stddev: f64,
+    /// Half-width of the 95% confidence interval of the mean.
+    pub ci95: f64,
+}
+
+impl Summary {
+    /// Summarizes `values`; every field is NaN when `values` is empty.
+    pub fn of(values: &[f64]) -> Summary {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            let sum_sq: f64 = sorted.iter().map(|v| (v - mean) * (v - mean)).sum();
+            (sum_sq / (n - 1) as f64).sqrt()
+        } else if n == 1 {
+            0.0
+        } else {
+            f64::NAN
+        };
+        let ci95 = if n > 1 {
+            t_critical_95(n - 1) * stddev / (n as f64).sqrt()
+        } else {
+            stddev
+        };
+        Summary {
+            min: sorted.first().copied().unwrap_or(f64::NAN),
+            max: sorted.last().copied().unwrap_or(f64::NAN),
+            mean,
+            median: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            stddev,
+            ci95,
+        }
+    }
+}
+
+/// The `p`th percentile of already sorted values, linearly interpolating
+/// between the closest ranks.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (p / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Two-sided 95% critical value of Student's t distribution.
+fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom {
+        0 => f64::NAN,
+        df if df <= TABLE.len() => TABLE[df - 1],
+        // close enough to the normal distribution
+        _ => 1.960,
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::io;
+
+use crate::codec::Stage;
+
+/// How many mismatches a failed verification lists before summarizing the rest.
+const MAX_REPORTED: usize = 10;
+
+/// A decoded value that differs from the original.
+#[derive(Clone, Copy, Debug)]
+pub struct Mismatch {
+    /// Index into the whole dataset, not just the page.
+    pub index: usize,
+    pub expected: f64,
+    pub actual: f64,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "index {}: expected {:?} ({:#018x}), got {:?} ({:#018x})",
+            self.index,
+            self.expected,
+            self.expected.to_bits(),
+            self.actual,
+            self.actual.to_bits()
+        )
+    }
+}
+
+/// Fails with `InvalidData` if a page did not decode to as many values as it was
+/// encoded from. Lossy codecs may change values, but never their number.
+pub fn same_len(codec: &str, offset: usize, expected: &[f64], actual: &[f64]) -> io::Result<()> {
+    if expected.len() == actual.len() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} decoded {} values at index {}, expected {}",
+            codec,
+            actual.len(),
+            offset,
+            expected.len()
+        ),
+    ))
+}
+
+/// Checks that `actual` holds exactly the bits of `expected`, so NaN payloads,
+/// -0.0 and infinities must survive the round trip as well. `offset` is the
+/// index of the page's first value within the dataset and is only used for
+/// reporting. Fails with `InvalidData` listing the first mismatches.
+pub fn bit_exact(codec: &str, offset: usize, expected: &[f64], actual: &[f64]) -> io::Result<()> {
+    same_len(codec, offset, expected, actual)?;
+
+    let mismatches: Vec<Mismatch> = expected
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (e, a))| e.to_bits() != a.to_bits())
+        .map(|(i, (&expected, &actual))| Mismatch {
+            index: offset + i,
+            expected,
+            actual,
+        })
+        .collect();
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "{} is not lossless: {} of {} values differ",
+        codec,
+        mismatches.len(),
+        expected.len()
+    );
+    for mismatch in mismatches.iter().take(MAX_REPORTED) {
+        message.push_str(&format!("\n  {}", mismatch));
+    }
+    if mismatches.len() > MAX_REPORTED {
+        message.push_str(&format!(
+            "\n  ... and {} more",
+            mismatches.len() - MAX_REPORTED
+        ));
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Checks the timestamps of a codec that stores them. Stages without
+/// timestamps pass, as there is nothing to compare.
+pub fn timestamps(codec: &str, offset: usize, expected: &[u64], decoded: &Stage) -> io::Result<()> {
+    let points = match decoded {
+        Stage::Points(points) => points,
+        _ => return Ok(()),
+    };
+    let mismatch = expected
+        .iter()
+        .zip(points)
+        .position(|(time, dp)| *time != dp.get_time());
+    match mismatch {
+        None => Ok(()),
+        Some(i) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} changed timestamps: index {}: expected {}, got {}",
+                codec,
+                offset + i,
+                expected[i],
+                points[i].get_time()
+            ),
+        )),
+    }
+}
+
+/// How far a lossy codec's output is from the original values.
+///
+/// Keeps running sums rather than the values themselves, so the errors of
+/// several pages can be combined with `merge`.
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorStats {
+    /// Absolute error a value may have to count as within tolerance.
+    pub tolerance: f64,
+    pub count: usize,
+    pub within_tolerance: usize,
+    pub max_abs_error: f64,
+    /// Relative to the original value; zeros are left out.
+    pub max_rel_error: f64,
+    sum_sq_error: f64,
+    min: f64,
+    max: f64,
+}
+
+impl ErrorStats {
+    pub fn new(tolerance: f64) -> ErrorStats {
+        ErrorStats {
+            tolerance,
+            count: 0,
+            within_tolerance: 0,
+            max_abs_error: 0.0,
+            max_rel_error: 0.0,
+            sum_sq_error: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Errors of a page; `expected` and `actual` must have the same length.
+    pub fn of(tolerance: f64, expected: &[f64], actual: &[f64]) -> ErrorStats {
+        let mut stats = ErrorStats::new(tolerance);
+        for (&e, &a) in expected.iter().zip(actual) {
+            stats.add(e, a);
+        }
+        stats
+    }
+
+    fn add(&mut self, expected: f64, actual: f64) {
+        // equal infinities and NaN for NaN are exact, anything else involving
+        // them is infinitely wrong
+        let abs_error = if expected == actual || (expected.is_nan() && actual.is_nan()) {
+            0.0
+        } else {
+            let diff = (expected - actual).abs();
+            if diff.is_nan() {
+                f64::INFINITY
+            } else {
+                diff
+            }
+        };
+        self.count += 1;
+        if abs_error <= self.tolerance {
+            self.within_tolerance += 1;
+        }
+        self.max_abs_error = self.max_abs_error.max(abs_error);
+        if expected != 0.0 && abs_error > 0.0 {
+            self.max_rel_error = self.max_rel_error.max(abs_error / expected.abs());
+        }
+        self.sum_sq_error += abs_error * abs_error;
+        if expected.is_finite() {
+            self.min = self.min.min(expected);
+            self.max = self.max.max(expected);
+        }
+    }
+
+    pub fn merge(&mut self, other: &ErrorStats) {
+        self.count += other.count;
+        self.within_tolerance += other.within_tolerance;
+        self.max_abs_error = self.max_abs_error.max(other.max_abs_error);
+        self.max_rel_error = self.max_rel_error.max(other.max_rel_error);
+        self.sum_sq_error += other.sum_sq_error;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Root mean square error.
+    pub fn rmse(&self) -> f64 {
+        (self.sum_sq_error / self.count as f64).sqrt()
+    }
+
+    /// Peak signal-to-noise ratio in dB, with the range of the original values
+    /// as the peak. Infinite for an exact round trip.
+    pub fn psnr(&self) -> f64 {
+        20.0 * ((self.max - self.min) / self.rmse()).log10()
+    }
+
+    /// Fraction of values whose absolute error is at most `tolerance`.
+    pub fn within_tolerance_fraction(&self) -> f64 {
+        self.within_tolerance as f64 / self.count as f64
+    }
+}
@@ -0,0 +1,253 @@
+//! Safe wrappers around `zfp_sys`.
+//!
+//! zfp needs three objects for every call: a field describing the array, a
+//! bit stream over the buffer and a zfp stream holding the compression mode.
+//! Each is owned by a guard that frees it on drop and borrows the memory it
+//! points into, so none of them can outlive their buffers or leak on an early
+//! return.
+//!
+//! zfp does not record the number of values, so a compressed page starts with
+//! it (u32, little endian), followed by the zfp stream. The mode is not stored
+//! either and has to be the same for compressing and decompressing. zfp writes
+//! 64 bit words in the machine's byte order, so pages only decompress on
+//! machines with the same byte order.
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::io;
+use std::marker::PhantomData;
+
+/// How zfp trades accuracy for size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZfpMode {
+    /// Lossless.
+    Reversible,
+    /// Every block of 4 values takes `4 * rate` bits.
+    FixedRate(f64),
+    /// Keeps this many bit planes of every block.
+    FixedPrecision(u32),
+    /// Keeps the absolute error at most this tolerance.
+    FixedAccuracy(f64),
+}
+
+impl ZfpMode {
+    pub fn lossy(&self) -> bool {
+        *self != ZfpMode::Reversible
+    }
+}
+
+fn zfp_error(msg: &str) -> io::Error {
+    io::Error::other(msg)
+}
+
+/// A 1d field of doubles, borrowing the array it describes.
+struct Field<'a> {
+    raw: *mut zfp_sys::zfp_field,
+    values: PhantomData<&'a mut [f64]>,
+}
+
+impl<'a> Field<'a> {
+    fn new(values: &'a mut [f64]) -> io::Result<Self> {
+        let raw = unsafe {
+            zfp_sys::zfp_field_1d(
+                values.as_mut_ptr() as *mut c_void,
+                zfp_sys::zfp_type_zfp_type_double,
+                values.len() as _,
+            )
+        };
+        if raw.is_null() {
+            return Err(zfp_error("zfp could not allocate a field"));
+        }
+        Ok(Field {
+            raw,
+            values: PhantomData,
+        })
+    }
+}
+
+impl Drop for Field<'_> {
+    fn drop(&mut self) {
+        unsafe { zfp_sys::zfp_field_free(self.raw) };
+    }
+}
+
+/// A bit stream over a buffer of words, which zfp reads and writes whole.
+struct BitStream<'a> {
+    raw: *mut zfp_sys::bitstream,
+    buffer: PhantomData<&'a mut [u64]>,
+}
+
+impl<'a> BitStream<'a> {
+    fn new(buffer: &'a mut [u64]) -> io::Result<Self> {
+        let raw = unsafe {
+            zfp_sys::stream_open(buffer.as_mut_ptr() as *mut c_void, (buffer.len() * 8) as _)
+        };
+        if raw.is_null() {
+            return Err(zfp_error("zfp could not open a bit stream"));
+        }
+        Ok(BitStream {
+            raw,
+            buffer: PhantomData,
+        })
+    }
+}
+
+impl Drop for BitStream<'_> {
+    fn drop(&mut self) {
+        unsafe { zfp_sys::stream_close(self.raw) };
+    }
+}
+
+/// A zfp stream in `mode`, without a bit stream attached yet.
+struct Stream {
+    raw: *mut zfp_sys::zfp_stream,
+}
+
+impl Stream {
+    fn new(mode: ZfpMode) -> io::Result<Self> {
+        let raw = unsafe { zfp_sys::zfp_stream_open(std::ptr::null_mut()) };
+        if raw.is_null() {
+            return Err(zfp_error("zfp could not open a stream"));
+        }
+        let stream = Stream { raw };
+        unsafe {
+            match mode {
+                ZfpMode::Reversible => zfp_sys::zfp_stream_set_reversible(raw),
+                ZfpMode::FixedRate(rate) => {
+                    zfp_sys::zfp_stream_set_rate(
+                        raw,
+                        rate,
+                        zfp_sys::zfp_type_zfp_type_double,
+                        1,
+                        0,
+                    );
+                }
+                ZfpMode::FixedPrecision(precision) => {
+                    zfp_sys::zfp_stream_set_precision(raw, precision as _);
+                }
+                ZfpMode::FixedAccuracy(tolerance) => {
+                    zfp_sys::zfp_stream_set_accuracy(raw, tolerance);
+                }
+            }
+        }
+        Ok(stream)
+    }
+
+    // a buffer of words large enough for anything `field` compresses to
+    fn buffer_for(&self, field: &Field) -> Vec<u64> {
+        let bytes = unsafe { zfp_sys::zfp_stream_maximum_size(self.raw, field.raw) } as usize;
+        vec![0; bytes.div_ceil(8)]
+    }
+
+    // attaches `bits`, which has to stay alive for as long as the stream is used
+    fn attach(&mut self, bits: &BitStream) {
+        unsafe {
+            zfp_sys::zfp_stream_set_bit_stream(self.raw, bits.raw);
+            zfp_sys::zfp_stream_rewind(self.raw);
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe { zfp_sys::zfp_stream_close(self.raw) };
+    }
+}
+
+pub fn zfp_compress(src: &[f64], mode: ZfpMode) -> io::Result<Vec<u8>> {
+    let count = u32::try_from(src.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "zfp pages hold at most u32::MAX values",
+        )
+    })?;
+    let mut dst = count.to_le_bytes().to_vec();
+    if src.is_empty() {
+        return Ok(dst);
+    }
+
+    // zfp only reads the field when compressing, but needs it mutable
+    let mut values = src.to_vec();
+    let field = Field::new(&mut values)?;
+    let mut stream = Stream::new(mode)?;
+    let mut buffer = stream.buffer_for(&field);
+    let compressed_bytes = {
+        let bits = BitStream::new(&mut buffer)?;
+        stream.attach(&bits);
+        let compressed_bytes = unsafe { zfp_sys::zfp_compress(stream.raw, field.raw) } as usize;
+        // detach before the bit stream is closed
+        unsafe { zfp_sys::zfp_stream_set_bit_stream(stream.raw, std::ptr::null_mut()) };
+        compressed_bytes
+    };
+    if compressed_bytes == 0 {
+        return Err(zfp_error("zfp compression failed"));
+    }
+
+    for word in &buffer {
+        dst.extend_from_slice(&word.to_ne_bytes());
+    }
+    dst.truncate(4 + compressed_bytes);
+    Ok(dst)
+}
+
+pub fn zfp_decompress(bytes: &[u8], mode: ZfpMode) -> io::Result<Vec<f64>> {
+    if bytes.len() < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "zfp page is truncated",
+        ));
+    }
+    let count = LittleEndian::read_u32(bytes) as usize;
+    // zfp writes at least one bit for every block of four values, so a count
+    // the page cannot hold is rejected before allocating for it
+    let compressed = &bytes[4..];
+    if compressed.len() * 8 < count.div_ceil(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "zfp page of {} values has only {} bytes",
+                count,
+                compressed.len()
+            ),
+        ));
+    }
+    let mut values = vec![0.0; count];
+    if count == 0 {
+        return Ok(values);
+    }
+
+    let field = Field::new(&mut values)?;
+    let mut stream = Stream::new(mode)?;
+    // a truncated page decodes from the zeros after it rather than reading
+    // past the end of the buffer
+    let mut buffer = stream.buffer_for(&field);
+    if compressed.len() > buffer.len() * 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "zfp page of {} values has {} bytes, more than zfp ever writes",
+                count,
+                compressed.len()
+            ),
+        ));
+    }
+    for (word, chunk) in buffer.iter_mut().zip(compressed.chunks(8)) {
+        let mut padded = [0; 8];
+        padded[..chunk.len()].copy_from_slice(chunk);
+        *word = u64::from_ne_bytes(padded);
+    }
+
+    let decompressed_bytes = {
+        let bits = BitStream::new(&mut buffer)?;
+        stream.attach(&bits);
+        let decompressed_bytes = unsafe { zfp_sys::zfp_decompress(stream.raw, field.raw) };
+        unsafe { zfp_sys::zfp_stream_set_bit_stream(stream.raw, std::ptr::null_mut()) };
+        decompressed_bytes
+    };
+    if decompressed_bytes == 0 {
+        return Err(zfp_error("zfp decompression failed"));
+    }
+    drop(field);
+    Ok(values)
+}